@@ -0,0 +1,239 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::bail;
+use clap::{Parser, Subcommand};
+use flexible::conf::config::{self, Account, ConfigData, Owner};
+use flexible::conf::{secret, validate};
+use flexible::dispatch::output::{self, Format};
+use flexible::dispatch::{self, Status};
+use flexible::plugins::{client, oauth};
+use flexible::{console, logging};
+
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Runs the flexible watcher without the Tauri window.
+#[derive(Parser)]
+#[command(name = "flexible-cli", version)]
+struct Cli {
+    /// Config file, defaults to the one saved by the app
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Log filter for stderr, e.g. info or flexible=debug. Overrides RUST_LOG, warn without either
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Poll on the configured dispatch interval until interrupted
+    Watch,
+    /// Poll once and print the pending reviews without notifying
+    Check {
+        /// One of json, table or count
+        #[arg(short, long, default_value = "table")]
+        format: Format,
+    },
+    /// List every open PR on the watched repos and why it would not be notified
+    Preview,
+    /// List the repositories of the configured owner, or of an organization
+    Repos {
+        #[arg(long)]
+        org: Option<String>,
+    },
+    /// List the organizations the token can see
+    Orgs,
+    /// Report every problem in the config file
+    ValidateConfig {
+        /// Also check that the token works and the repos exist
+        #[arg(long)]
+        online: bool,
+    },
+    /// Sign in through the forge's device flow and keep the token in the OS keychain
+    Login {
+        /// Name of the account to sign in, defaults to the top-level one
+        #[arg(long)]
+        account: Option<String>,
+    },
+    /// Forget the token stored by login
+    Logout {
+        #[arg(long)]
+        account: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    logging::init(None)?;
+    match &cli.log_level {
+        Some(level) => logging::set_level(level)?,
+        None if env::var_os("RUST_LOG").is_none() => logging::set_level("warn")?,
+        None => {}
+    }
+    let path = cli.config.unwrap_or_else(config::default_path);
+    let mut conf = ConfigData::load(&path)
+        .map_err(|err| anyhow::anyhow!("reading {}: {}", path.display(), err))?;
+    if !matches!(cli.command, Command::Login { .. } | Command::Logout { .. }) {
+        oauth::resolve_tokens(&mut conf).await?;
+    }
+
+    match cli.command {
+        Command::Watch => watch(valid(conf)?).await?,
+        Command::Check { format } => {
+            let check = dispatch::check(valid(conf)?).await;
+            if let Some(err) = &check.error {
+                eprintln!("error: {}", err);
+            }
+            println!("{}", output::render(format, &check));
+        }
+        Command::Preview => {
+            let previews = dispatch::preview(valid(conf)?).await?;
+            for (target, err) in &previews.errors {
+                eprintln!("error: {target}: {err}");
+            }
+            for preview in previews.pulls {
+                let review = &preview.review;
+                println!(
+                    "{}\t{}#{}\t{}\t{}",
+                    preview.rejected.as_deref().unwrap_or("notify"),
+                    review.repo,
+                    review.number,
+                    review.title,
+                    review.link
+                );
+            }
+        }
+        Command::Repos { org } => {
+            let result = match org {
+                Some(name) => {
+                    conf.account.owners = Owner {
+                        name,
+                        repos: Vec::new(),
+                    };
+                    console::api::org_repos(conf).await
+                }
+                None => console::api::repos(conf).await,
+            };
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+        Command::Orgs => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&console::api::orgs(conf).await)?
+            );
+        }
+        Command::ValidateConfig { online } => {
+            let report = if online {
+                validate::validate_online(&conf).await
+            } else {
+                validate::validate(&conf)
+            };
+            for issue in &report.issues {
+                println!("{:?}\t{}\t{}", issue.severity, issue.path, issue.message);
+            }
+            if !report.is_valid() {
+                bail!("{} is not valid", path.display());
+            }
+            println!("{}: ok", path.display());
+        }
+        Command::Login { account } => login(&conf, find(&conf, account)?).await?,
+        Command::Logout { account } => secret::delete(find(&conf, account)?.name()).await?,
+    }
+    Ok(())
+}
+
+fn valid(conf: ConfigData) -> Result<ConfigData, anyhow::Error> {
+    conf.valid()?;
+    Ok(conf)
+}
+
+fn find(conf: &ConfigData, name: Option<String>) -> Result<&Account, anyhow::Error> {
+    match name {
+        None => Ok(&conf.account),
+        Some(name) => match conf.accounts().find(|account| account.name() == name) {
+            Some(account) => Ok(account),
+            None => bail!("no account named {name}"),
+        },
+    }
+}
+
+async fn login(conf: &ConfigData, account: &Account) -> Result<(), anyhow::Error> {
+    let endpoints = oauth::Endpoints::resolve(account)?;
+    let credential = client::scoped(client::build(&conf.http)?, async {
+        let code = oauth::start(&endpoints).await?;
+        println!(
+            "Open {} and enter the code {}",
+            code.verification_uri, code.user_code
+        );
+        oauth::poll(&endpoints, &code).await
+    })
+    .await?;
+    secret::store(account.name(), &credential).await?;
+    println!("Signed in, leave token empty in the config to use it");
+    Ok(())
+}
+
+async fn watch(conf: ConfigData) -> Result<(), anyhow::Error> {
+    let mut rx = dispatch::subscribe();
+    dispatch::execute(conf).await?;
+
+    loop {
+        tokio::select! {
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let status = rx.borrow_and_update().clone();
+                print(&status);
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    dispatch::shutdown(SHUTDOWN_DEADLINE).await;
+    Ok(())
+}
+
+fn print(status: &Status) {
+    if let Some(err) = &status.error {
+        eprintln!("error: {}", err);
+    }
+    for (repo, err) in &status.issues {
+        eprintln!("{repo}: issues: {}", err);
+    }
+    for review in &status.reviews {
+        println!(
+            "{}\t{}#{}\t{}\t{}",
+            review.account, review.repo, review.number, review.title, review.link
+        );
+    }
+    for event in &status.events {
+        println!(
+            "{}\t{}#{}\t{}: {}\t{}",
+            event.account, event.repo, event.number, event.reason, event.title, event.link
+        );
+    }
+}
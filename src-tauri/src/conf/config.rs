@@ -16,72 +16,250 @@
  */
 
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+use crate::conf::filter::{
+    AuthoredFilter, CiFilter, ConflictFilter, IssueFilter, LifecycleFilter, PullFilter,
+};
+use crate::conf::validate;
+use crate::error::Error;
+
+/// Where the app saves the config it was started with, shared with `flexible-cli`.
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("flexible")
+        .join("config.json")
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ConfigData {
-    pub plugin: String,
-    pub token: String,
-    pub reviews: Vec<String>,
-    pub owners: Owner,
-    pub orgs: HashMap<String, Vec<String>>,
+    /// The account edited in the window, kept at the top level so older configs still load.
+    #[serde(flatten)]
+    pub account: Account,
+    /// More accounts watched alongside it, e.g. a GitHub Enterprise instance.
+    #[serde(default)]
+    pub accounts: Vec<Account>,
     pub dispatch: u64,
+    #[serde(default)]
+    pub http: Http,
 }
 
 impl ConfigData {
     pub fn new(plugin: &str, token: &str) -> Self {
-        ConfigData {
-            plugin: plugin.to_string(),
-            token: token.to_string(),
-            reviews: vec![],
-            owners: Owner {
-                name: "".to_string(),
-                repos: vec![],
-            },
-            orgs: HashMap::new(),
-            dispatch: 0,
-        }
+        Self::new_owner(plugin, token, Owner::default())
     }
 
     pub fn new_owner(plugin: &str, token: &str, owner: Owner) -> Self {
         ConfigData {
-            plugin: plugin.to_string(),
-            token: token.to_string(),
-            reviews: vec![],
-            owners: owner,
-            orgs: HashMap::new(),
+            account: Account {
+                plugin: plugin.to_string(),
+                token: token.to_string(),
+                owners: owner,
+                ..Default::default()
+            },
+            accounts: vec![],
             dispatch: 0,
+            http: Http::default(),
         }
     }
 
-    pub fn valid(&self) -> &str {
-        if self.token.is_empty() {
-            "token not allowed empty"
-        } else if self.plugin.is_empty() {
-            "plugin not allowed empty"
-        } else if self.dispatch == 0 {
-            "dispatch not allowed empty"
-        } else if self.reviews.is_empty() || self.reviews.iter().any(|s| s.is_empty()) {
-            "reviews not allowed empty"
-        } else if self.orgs.is_empty()
-            && (self.owners.name.is_empty()
-                || self.owners.repos.is_empty()
-                || self.owners.repos.iter().any(|s| s.is_empty()))
+    /// Every account to watch, the top-level one first unless only `accounts` is used.
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.has_primary()
+            .then_some(&self.account)
+            .into_iter()
+            .chain(&self.accounts)
+    }
+
+    pub fn accounts_mut(&mut self) -> impl Iterator<Item = &mut Account> {
+        let primary = self.has_primary();
+        primary
+            .then_some(&mut self.account)
+            .into_iter()
+            .chain(&mut self.accounts)
+    }
+
+    pub(crate) fn has_primary(&self) -> bool {
+        !self.account.plugin.is_empty() || self.accounts.is_empty()
+    }
+
+    pub fn valid(&self) -> Result<(), Error> {
+        validate::validate(self).into_result()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut value = serde_json::to_value(self)?;
+        if self.account.signed_in {
+            value["token"] = "".into();
+        }
+        for (i, account) in self.accounts.iter().enumerate() {
+            if account.signed_in {
+                value["accounts"][i]["token"] = "".into();
+            }
+        }
+        let content = serde_json::to_string_pretty(&value)?;
+
+        // The file holds the token, so keep it private to the user.
+        #[cfg(unix)]
         {
-            "owner/repos or orgs/repos not allowed empty"
+            use std::io::Write;
+            use std::os::unix::fs::OpenOptionsExt;
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(path)?
+                .write_all(content.as_bytes())?;
+        }
+        #[cfg(not(unix))]
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// One login on a forge and what to watch with it.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Account {
+    /// Tags notifications and names the keychain entry, defaults to the plugin.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub plugin: String,
+    /// Web address of a self-hosted forge, e.g. `https://github.example.com`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    pub token: String,
+    pub reviews: Vec<String>,
+    pub owners: Owner,
+    pub orgs: HashMap<String, Vec<String>>,
+    pub filter: RepoFilter,
+    pub pulls: PullFilter,
+    pub issues: IssueFilter,
+    pub authored: AuthoredFilter,
+    pub ci: CiFilter,
+    pub conflicts: ConflictFilter,
+    pub lifecycle: LifecycleFilter,
+    pub oauth: OAuth,
+    /// Set when `token` came from the secret store, which keeps it out of the saved file.
+    #[serde(skip)]
+    pub signed_in: bool,
+}
+
+impl Account {
+    pub fn name(&self) -> &str {
+        if self.name.is_empty() {
+            self.plugin.as_str()
+        } else {
+            self.name.as_str()
+        }
+    }
+
+    /// Who issue alerts are for.
+    pub fn issue_users(&self) -> &[String] {
+        self.users_or_reviewers(&self.issues.users)
+    }
+
+    /// Whose PRs the authored watch follows, none when it is off.
+    pub fn authored_users(&self) -> &[String] {
+        if !self.authored.enabled {
+            return &[];
+        }
+        self.users_or_reviewers(&self.authored.users)
+    }
+
+    fn users_or_reviewers<'a>(&'a self, users: &'a [String]) -> &'a [String] {
+        if users.is_empty() {
+            &self.reviews
         } else {
-            ""
+            users
         }
     }
 
     pub fn reviews(&self) -> HashMap<String, ()> {
         self.reviews.iter().map(|key| (key.clone(), ())).collect()
     }
+
+    /// Names a watched repo uniquely across accounts, e.g. `github:apache/flexible`.
+    pub fn target(&self, owner: &str, repo: &str) -> String {
+        format!("{}:{owner}/{repo}", self.name())
+    }
+}
+
+/// Which repos a pattern may select, repos listed by name are always watched.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RepoFilter {
+    pub archived: bool,
+    pub forks: bool,
+}
+
+impl Default for RepoFilter {
+    fn default() -> Self {
+        RepoFilter {
+            archived: false,
+            forks: true,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Owner {
     pub name: String,
     pub repos: Vec<String>,
 }
+
+/// Settings for the client shared by all plugins, timeouts are in seconds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Http {
+    pub connect_timeout: u64,
+    pub read_timeout: u64,
+    /// e.g. `http://proxy.corp:3128`, used for both http and https.
+    pub proxy: Option<String>,
+    /// Hosts that bypass the proxy, e.g. `localhost` or `.corp.example.com`.
+    pub no_proxy: Vec<String>,
+    /// PEM files with extra CAs to trust.
+    pub root_certificates: Vec<PathBuf>,
+    /// PEM certificate and PKCS#8 key for servers that require a client certificate.
+    pub client_certificate: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub user_agent: Option<String>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Http {
+            connect_timeout: 3,
+            read_timeout: 10,
+            proxy: None,
+            no_proxy: vec![],
+            root_certificates: vec![],
+            client_certificate: None,
+            client_key: None,
+            user_agent: None,
+        }
+    }
+}
+
+/// Device-flow sign in, every field falls back to the plugin's own value when unset.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct OAuth {
+    pub client_id: Option<String>,
+    pub device_url: Option<String>,
+    pub token_url: Option<String>,
+    pub scopes: Option<Vec<String>>,
+}
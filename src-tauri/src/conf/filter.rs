@@ -0,0 +1,444 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::conf::selector::Pattern;
+use crate::plugins::api::{CiState, Lifecycle, PullState};
+
+/// Which open PRs with a watched reviewer are notified, everything passes by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PullFilter {
+    pub drafts: bool,
+    /// Authors such as `dependabot[bot]` or `renovate[bot]`.
+    pub bots: bool,
+    /// Notify only PRs with one of these labels.
+    pub labels: Vec<String>,
+    pub exclude_labels: Vec<String>,
+    /// Author names or patterns to notify only, e.g. `alice` or `team-*`.
+    pub authors: Vec<String>,
+    pub exclude_authors: Vec<String>,
+    /// Base branch names or patterns, e.g. `main` or `release/*`.
+    pub base: Vec<String>,
+    /// Bounds on added plus deleted lines, checking them costs one request per PR.
+    pub min_changes: Option<u64>,
+    pub max_changes: Option<u64>,
+}
+
+impl Default for PullFilter {
+    fn default() -> Self {
+        PullFilter {
+            drafts: true,
+            bots: true,
+            labels: vec![],
+            exclude_labels: vec![],
+            authors: vec![],
+            exclude_authors: vec![],
+            base: vec![],
+            min_changes: None,
+            max_changes: None,
+        }
+    }
+}
+
+/// The parts of a PR a [`PullFilter`] looks at, filled in by each plugin.
+pub struct Candidate<'a> {
+    pub draft: bool,
+    pub labels: Vec<&'a str>,
+    pub author: &'a str,
+    pub bot: bool,
+    pub base: &'a str,
+}
+
+/// Names like `dependabot[bot]` hold glob syntax, so an exact match is tried first.
+fn any(patterns: &[String], value: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        pattern.eq_ignore_ascii_case(value)
+            || Pattern::parse(pattern).is_ok_and(|pattern| pattern.matches(value))
+    })
+}
+
+impl PullFilter {
+    /// Why `pr` is filtered out, `None` when it passes.
+    pub fn reject(&self, pr: &Candidate) -> Option<String> {
+        if pr.draft && !self.drafts {
+            return Some("draft".to_string());
+        }
+        if pr.bot && !self.bots {
+            return Some(format!("bot author {}", pr.author));
+        }
+        if !self.authors.is_empty() && !any(&self.authors, pr.author) {
+            return Some(format!("author {} not listed", pr.author));
+        }
+        if any(&self.exclude_authors, pr.author) {
+            return Some(format!("author {} excluded", pr.author));
+        }
+        let has = |label: &String| pr.labels.iter().any(|l| l.eq_ignore_ascii_case(label));
+        if !self.labels.is_empty() && !self.labels.iter().any(has) {
+            return Some("no listed label".to_string());
+        }
+        if let Some(label) = self.exclude_labels.iter().find(|label| has(label)) {
+            return Some(format!("label {label} excluded"));
+        }
+        if !self.base.is_empty() && !any(&self.base, pr.base) {
+            return Some(format!("base {} not listed", pr.base));
+        }
+        None
+    }
+
+    pub fn checks_size(&self) -> bool {
+        self.min_changes.is_some() || self.max_changes.is_some()
+    }
+
+    pub fn reject_size(&self, changes: u64) -> Option<String> {
+        if self.min_changes.is_some_and(|min| changes < min) {
+            return Some(format!("{changes} changed lines, below min_changes"));
+        }
+        if self.max_changes.is_some_and(|max| changes > max) {
+            return Some(format!("{changes} changed lines, above max_changes"));
+        }
+        None
+    }
+}
+
+/// Which issues to alert on, off unless `enabled`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IssueFilter {
+    pub enabled: bool,
+    /// Who to alert for, defaults to the account's reviewers.
+    pub users: Vec<String>,
+    /// Issues assigned to one of `users`.
+    pub assigned: bool,
+    /// Issues with any of these labels, e.g. `needs-triage` or `P0`.
+    pub labels: Vec<String>,
+    /// `@user` in an issue or PR description or comment.
+    pub mentions: bool,
+    /// Notification templates, with `{account}`, `{repo}`, `{number}`, `{title}`, `{link}`
+    /// and `{reason}` filled in.
+    pub title: String,
+    pub body: String,
+}
+
+impl Default for IssueFilter {
+    fn default() -> Self {
+        IssueFilter {
+            enabled: false,
+            users: vec![],
+            assigned: true,
+            labels: vec![],
+            mentions: true,
+            title: "{reason} in {repo}".to_string(),
+            body: "#{number} {title}\n{link}".to_string(),
+        }
+    }
+}
+
+/// The parts of an issue an [`IssueFilter`] looks at, filled in by each plugin.
+pub struct Issue<'a> {
+    pub pull: bool,
+    pub assignees: Vec<&'a str>,
+    pub labels: Vec<&'a str>,
+    pub body: &'a str,
+}
+
+impl IssueFilter {
+    /// Why `issue` deserves an alert, `None` when it does not.
+    pub fn reason(&self, users: &[String], issue: &Issue) -> Option<String> {
+        if let Some(user) = self.mentioned(users, issue.body) {
+            return Some(format!("{user} mentioned"));
+        }
+        // Assignment and labels are triage signals, PRs are covered by review requests.
+        if issue.pull {
+            return None;
+        }
+        if self.assigned {
+            if let Some(user) = users
+                .iter()
+                .find(|user| issue.assignees.iter().any(|a| a.eq_ignore_ascii_case(user)))
+            {
+                return Some(format!("assigned to {user}"));
+            }
+        }
+        self.labels
+            .iter()
+            .find(|label| issue.labels.iter().any(|l| l.eq_ignore_ascii_case(label)))
+            .map(|label| format!("labeled {label}"))
+    }
+
+    /// The first of `users` that `text` @mentions.
+    pub fn mentioned<'u>(&self, users: &'u [String], text: &str) -> Option<&'u str> {
+        if !self.mentions {
+            return None;
+        }
+        let text = text.to_lowercase();
+        users.iter().map(String::as_str).find(|user| {
+            let mention = format!("@{}", user.to_lowercase());
+            text.match_indices(&mention).any(|(at, _)| {
+                let before = text[..at].chars().next_back();
+                let after = text[at + mention.len()..].chars().next();
+                !before.is_some_and(|c| c.is_alphanumeric())
+                    && !after.is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            })
+        })
+    }
+}
+
+/// Alerts on PRs written by `users` as they get reviewed or become mergeable, off unless
+/// `enabled`. The templates take the same fields as [`IssueFilter`]'s.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AuthoredFilter {
+    pub enabled: bool,
+    /// Defaults to the account's reviewers.
+    pub users: Vec<String>,
+    pub title: String,
+    pub body: String,
+}
+
+impl Default for AuthoredFilter {
+    fn default() -> Self {
+        AuthoredFilter {
+            enabled: false,
+            users: vec![],
+            title: "{reason} on {repo}#{number}".to_string(),
+            body: "{title}\n{link}".to_string(),
+        }
+    }
+}
+
+/// Alerts on CI state changes of tracked PRs, off unless `enabled`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CiFilter {
+    pub enabled: bool,
+    /// States to notify for PRs of the authored watch.
+    pub authored: Vec<CiState>,
+    /// States to notify for PRs awaiting the account's review.
+    pub reviews: Vec<CiState>,
+    /// Hold review request notifications until the PR's CI has passed.
+    pub wait_for_green: bool,
+    pub title: String,
+    pub body: String,
+}
+
+impl Default for CiFilter {
+    fn default() -> Self {
+        CiFilter {
+            enabled: false,
+            authored: vec![CiState::Success, CiState::Failure],
+            reviews: vec![CiState::Success],
+            wait_for_green: false,
+            title: "{reason} on {repo}#{number}".to_string(),
+            body: "{title}\n{link}".to_string(),
+        }
+    }
+}
+
+impl CiFilter {
+    /// Whether `pull` reaching `state` is notified.
+    pub fn notifies(&self, pull: &PullState, state: CiState) -> bool {
+        (pull.authored && self.authored.contains(&state))
+            || (pull.review_requested && self.reviews.contains(&state))
+    }
+
+    /// Whether a review request for a PR in CI state `ci` is held back.
+    pub fn holds(&self, ci: Option<CiState>) -> bool {
+        self.enabled && self.wait_for_green && ci.is_some_and(|ci| ci != CiState::Success)
+    }
+}
+
+/// Alerts when a tracked PR gets a merge conflict or falls behind its base, off unless `enabled`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConflictFilter {
+    pub enabled: bool,
+    /// Commits behind the base branch that make a PR stale, 0 to not check.
+    pub behind: u64,
+    pub title: String,
+    pub body: String,
+}
+
+impl Default for ConflictFilter {
+    fn default() -> Self {
+        ConflictFilter {
+            enabled: false,
+            behind: 0,
+            title: "{reason} on {repo}#{number}".to_string(),
+            body: "{title}\n{link}".to_string(),
+        }
+    }
+}
+
+/// Alerts when PRs on the watched repos open, close, merge or change, off unless `enabled`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LifecycleFilter {
+    pub enabled: bool,
+    /// Which events to notify, all of them by default.
+    pub events: Vec<Lifecycle>,
+    pub title: String,
+    pub body: String,
+}
+
+impl Default for LifecycleFilter {
+    fn default() -> Self {
+        LifecycleFilter {
+            enabled: false,
+            events: Lifecycle::ALL.to_vec(),
+            title: "{repo}#{number} {reason}".to_string(),
+            body: "{title}\n{link}".to_string(),
+        }
+    }
+}
+
+impl LifecycleFilter {
+    pub fn notifies(&self, event: Lifecycle) -> bool {
+        self.enabled && self.events.contains(&event)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::filter::{Candidate, CiFilter, Issue, IssueFilter, PullFilter};
+    use crate::plugins::api::{CiState, PullState};
+
+    fn pr() -> Candidate<'static> {
+        Candidate {
+            draft: false,
+            labels: vec!["ready"],
+            author: "renovate[bot]",
+            bot: true,
+            base: "release/1.0",
+        }
+    }
+
+    #[test]
+    fn test_default_passes() {
+        let filter = PullFilter::default();
+        assert_eq!(filter.reject(&pr()), None);
+        assert!(!filter.checks_size());
+    }
+
+    #[test]
+    fn test_reject() {
+        let filter = PullFilter {
+            bots: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.reject(&pr()),
+            Some("bot author renovate[bot]".to_string())
+        );
+
+        let filter = PullFilter {
+            labels: vec!["Ready".to_string()],
+            exclude_authors: vec!["renovate*".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.reject(&pr()),
+            Some("author renovate[bot] excluded".to_string())
+        );
+
+        let filter = PullFilter {
+            base: vec!["main".to_string(), "release/*".to_string()],
+            max_changes: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(filter.reject(&pr()), None);
+        assert_eq!(
+            filter.reject_size(501),
+            Some("501 changed lines, above max_changes".to_string())
+        );
+
+        let filter = PullFilter {
+            exclude_authors: vec!["Dependabot[bot]".to_string()],
+            ..Default::default()
+        };
+        let dependabot = Candidate {
+            author: "dependabot[bot]",
+            ..pr()
+        };
+        assert_eq!(
+            filter.reject(&dependabot),
+            Some("author dependabot[bot] excluded".to_string())
+        );
+        assert_eq!(filter.reject(&pr()), None);
+    }
+
+    #[test]
+    fn test_issue_reason() {
+        let filter = IssueFilter {
+            labels: vec!["P0".to_string()],
+            ..Default::default()
+        };
+        let users = ["alice".to_string()];
+        let issue = |pull, assignees, labels, body| Issue {
+            pull,
+            assignees,
+            labels,
+            body,
+        };
+
+        assert_eq!(
+            filter.reason(&users, &issue(false, vec!["Alice"], vec![], "")),
+            Some("assigned to alice".to_string())
+        );
+        assert_eq!(
+            filter.reason(&users, &issue(false, vec![], vec!["p0"], "")),
+            Some("labeled P0".to_string())
+        );
+        assert_eq!(
+            filter.reason(
+                &users,
+                &issue(true, vec!["alice"], vec!["P0"], "cc @alice.")
+            ),
+            Some("alice mentioned".to_string())
+        );
+        assert_eq!(
+            filter.reason(
+                &users,
+                &issue(true, vec!["alice"], vec![], "mail alice@x.org, @alice-bot")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_wait_for_green() {
+        let filter = CiFilter {
+            enabled: true,
+            wait_for_green: true,
+            ..Default::default()
+        };
+        assert!(filter.holds(Some(CiState::Pending)));
+        assert!(filter.holds(Some(CiState::Failure)));
+        assert!(!filter.holds(Some(CiState::Success)));
+        // Repos without CI are never held.
+        assert!(!filter.holds(None));
+
+        let pull = PullState {
+            review_requested: true,
+            ..Default::default()
+        };
+        assert!(filter.notifies(&pull, CiState::Success));
+        assert!(!filter.notifies(&pull, CiState::Pending));
+        assert!(!CiFilter::default().holds(Some(CiState::Pending)));
+    }
+}
@@ -16,3 +16,7 @@
  */
 
 pub mod config;
+pub mod filter;
+pub mod secret;
+pub mod selector;
+pub mod validate;
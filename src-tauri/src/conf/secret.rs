@@ -0,0 +1,90 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+const SERVICE: &str = "flexible";
+
+/// A token obtained by signing in, kept in the OS keychain instead of the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Credential {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix seconds after which `access_token` must be refreshed.
+    pub expires_at: Option<u64>,
+}
+
+impl Credential {
+    pub fn is_expired(&self, now: u64) -> bool {
+        // Refresh a minute early so a tick never starts with a token about to lapse.
+        self.expires_at.is_some_and(|at| at <= now + 60)
+    }
+}
+
+fn entry(account: &str) -> Result<keyring::Entry, Error> {
+    keyring::Entry::new(SERVICE, account).map_err(secret_error)
+}
+
+fn secret_error(err: keyring::Error) -> Error {
+    Error::Secret(err.to_string())
+}
+
+// Keychain backends block, and some start their own runtime, so keep them off the async workers.
+async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Secret(err.to_string()))?
+}
+
+pub async fn load(account: &str) -> Result<Option<Credential>, Error> {
+    let account = account.to_string();
+    blocking(move || match entry(&account)?.get_password() {
+        Ok(content) => serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|err| Error::Secret(err.to_string())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(secret_error(err)),
+    })
+    .await
+}
+
+pub async fn store(account: &str, credential: &Credential) -> Result<(), Error> {
+    let account = account.to_string();
+    let content =
+        serde_json::to_string(credential).map_err(|err| Error::Secret(err.to_string()))?;
+    blocking(move || {
+        entry(&account)?
+            .set_password(&content)
+            .map_err(secret_error)
+    })
+    .await
+}
+
+pub async fn delete(account: &str) -> Result<(), Error> {
+    let account = account.to_string();
+    blocking(move || match entry(&account)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(secret_error(err)),
+    })
+    .await
+}
@@ -0,0 +1,147 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex_automata::meta::Regex;
+
+/// One entry of a repo list.
+#[derive(Debug)]
+pub enum Pattern {
+    Exact(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Parses `/^api-\d+$/` as a regex, `api-*` as a glob and anything else as a repo name.
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        if let Some(re) = entry
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            return Regex::new(re)
+                .map(Pattern::Regex)
+                .map_err(|err| format!("invalid regex {entry}: {err}"));
+        }
+        if entry.contains(['*', '?', '[', '{']) {
+            return GlobBuilder::new(entry)
+                .case_insensitive(true)
+                .build()
+                .map(|glob| Pattern::Glob(glob.compile_matcher()))
+                .map_err(|err| format!("invalid glob {entry}: {err}"));
+        }
+        Ok(Pattern::Exact(entry.to_string()))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            // Forges treat repo names case-insensitively.
+            Pattern::Exact(exact) => exact.eq_ignore_ascii_case(name),
+            Pattern::Glob(glob) => glob.is_match(name),
+            Pattern::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+/// A parsed repo list, entries starting with `!` exclude what they match.
+#[derive(Debug, Default)]
+pub struct Selection {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Selection {
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        let mut selection = Selection::default();
+        for entry in entries {
+            match entry.strip_prefix('!') {
+                Some(excluded) => selection.exclude.push(Pattern::parse(excluded)?),
+                None => selection.include.push(Pattern::parse(entry)?),
+            }
+        }
+        Ok(selection)
+    }
+
+    /// Whether the list only names repos, so it needs no listing from the forge.
+    pub fn is_static(&self) -> bool {
+        self.exclude.is_empty()
+            && self
+                .include
+                .iter()
+                .all(|pattern| matches!(pattern, Pattern::Exact(_)))
+    }
+
+    /// Repos named exactly, plus the `listed` ones a pattern matches, minus the excluded ones.
+    pub fn select<'a>(&self, listed: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let excluded = |name: &str| self.exclude.iter().any(|pattern| pattern.matches(name));
+        // Only exclusions means everything else.
+        let everything = self.include.is_empty();
+
+        let mut selected: Vec<String> = self
+            .include
+            .iter()
+            .filter_map(|pattern| match pattern {
+                Pattern::Exact(name) if !excluded(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        for name in listed {
+            let included = everything
+                || self
+                    .include
+                    .iter()
+                    .any(|pattern| !matches!(pattern, Pattern::Exact(_)) && pattern.matches(name));
+            if included && !excluded(name) && !selected.iter().any(|s| s.eq_ignore_ascii_case(name))
+            {
+                selected.push(name.to_string());
+            }
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::selector::{Pattern, Selection};
+
+    fn selection(entries: &[&str]) -> Selection {
+        Selection::parse(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert!(Pattern::parse("Flexible").unwrap().matches("flexible"));
+        assert!(Pattern::parse("api-*").unwrap().matches("api-gateway"));
+        assert!(!Pattern::parse("api-*").unwrap().matches("web"));
+        assert!(Pattern::parse(r"/^svc-\d+$/").unwrap().matches("svc-42"));
+        assert!(!Pattern::parse(r"/^svc-\d+$/").unwrap().matches("svc-x"));
+        assert!(Pattern::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let listed = ["api-a", "api-b", "web", "docs"];
+        assert!(selection(&["flexible", "web"]).is_static());
+        assert!(!selection(&["*"]).is_static());
+        assert_eq!(
+            selection(&["api-*", "!api-b", "private"]).select(listed),
+            vec!["private", "api-a"]
+        );
+        assert_eq!(selection(&["!docs", "!/^api/"]).select(listed), vec!["web"]);
+        assert_eq!(selection(&["*"]).select(listed).len(), 4);
+    }
+}
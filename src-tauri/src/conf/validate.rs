@@ -0,0 +1,398 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::de::IgnoredAny;
+use serde::Serialize;
+
+use crate::conf::config::{Account, ConfigData};
+use crate::conf::selector::Pattern;
+use crate::error::Error;
+use crate::plugins::{client, get_api, get_client};
+
+/// Polling faster than this burns through the hourly API rate limit on larger watch lists.
+const MIN_DISPATCH: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub path: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.push(path.into(), Severity::Error, message.into());
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.push(path.into(), Severity::Warning, message.into());
+    }
+
+    fn push(&mut self, path: String, severity: Severity, message: String) {
+        self.issues.push(Issue {
+            path,
+            severity,
+            message,
+        });
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|issue| issue.severity != Severity::Error)
+    }
+
+    /// The first error, for callers that only need a pass or fail.
+    pub fn into_result(self) -> Result<(), Error> {
+        match self
+            .issues
+            .into_iter()
+            .find(|issue| issue.severity == Severity::Error)
+        {
+            Some(issue) => Err(Error::Config(format!("{}: {}", issue.path, issue.message))),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn validate(conf: &ConfigData) -> Report {
+    let mut report = Report::default();
+
+    if conf.has_primary() {
+        account(&mut report, "", &conf.account);
+    }
+
+    if conf.dispatch == 0 {
+        report.error("dispatch", "not allowed empty");
+    } else if conf.dispatch < MIN_DISPATCH {
+        report.warning(
+            "dispatch",
+            format!("polling more often than every {MIN_DISPATCH}s may hit the API rate limit"),
+        );
+    }
+
+    let mut names = conf
+        .has_primary()
+        .then(|| conf.account.name())
+        .into_iter()
+        .collect::<Vec<_>>();
+    for (i, extra) in conf.accounts.iter().enumerate() {
+        let prefix = format!("accounts[{i}].");
+        account(&mut report, &prefix, extra);
+        if names.contains(&extra.name()) {
+            report.error(
+                format!("{prefix}name"),
+                format!("{} is already used by another account", extra.name()),
+            );
+        }
+        names.push(extra.name());
+    }
+
+    if let Err(err) = client::build(&conf.http) {
+        report.error("http", err.to_string());
+    }
+
+    report
+}
+
+fn account(report: &mut Report, prefix: &str, account: &Account) {
+    if account.token.is_empty() {
+        report.error(format!("{prefix}token"), "not allowed empty");
+    }
+    if account.plugin.is_empty() {
+        report.error(format!("{prefix}plugin"), "not allowed empty");
+    } else if let Err(err) = get_api(account, "".to_string()) {
+        report.error(format!("{prefix}plugin"), err.to_string());
+    }
+
+    if account.issues.enabled && account.plugin != "github" {
+        report.warning(
+            format!("{prefix}issues"),
+            format!("issue alerts are not read from {}", account.plugin),
+        );
+    }
+    if account.conflicts.enabled && account.conflicts.behind > 0 && account.plugin != "github" {
+        report.warning(
+            format!("{prefix}conflicts.behind"),
+            format!("stale branches are not read from {}", account.plugin),
+        );
+    }
+
+    if account.reviews.is_empty() {
+        report.error(format!("{prefix}reviews"), "not allowed empty");
+    }
+    for (i, review) in account.reviews.iter().enumerate() {
+        if review.is_empty() {
+            report.error(format!("{prefix}reviews[{i}]"), "not allowed empty");
+        } else if account.reviews[..i].contains(review) {
+            report.warning(
+                format!("{prefix}reviews[{i}]"),
+                format!("{review} is listed twice"),
+            );
+        }
+    }
+
+    let owner = &account.owners;
+    if !owner.name.is_empty() && owner.repos.is_empty() {
+        report.error(
+            format!("{prefix}owners.repos"),
+            "not allowed empty when owners.name is set",
+        );
+    }
+    if owner.name.is_empty() && !owner.repos.is_empty() {
+        report.error(
+            format!("{prefix}owners.name"),
+            "not allowed empty when owners.repos is set",
+        );
+    }
+    repos(report, &format!("{prefix}owners.repos"), &owner.repos);
+
+    for (org, org_repos) in &account.orgs {
+        if org.is_empty() {
+            report.error(format!("{prefix}orgs"), "org name not allowed empty");
+        } else if org_repos.is_empty() {
+            report.error(
+                format!("{prefix}orgs.{org}"),
+                "not allowed empty, use * to watch every repo",
+            );
+        }
+        repos(report, &format!("{prefix}orgs.{org}"), org_repos);
+    }
+
+    if owner.name.is_empty() && account.orgs.is_empty() {
+        report.error(
+            format!("{prefix}owners"),
+            "owner/repos or orgs/repos not allowed empty",
+        );
+    }
+
+    let pulls = &account.pulls;
+    for (field, patterns) in [
+        ("authors", &pulls.authors),
+        ("exclude_authors", &pulls.exclude_authors),
+        ("base", &pulls.base),
+    ] {
+        for (i, pattern) in patterns.iter().enumerate() {
+            if let Err(err) = Pattern::parse(pattern) {
+                report.error(format!("{prefix}pulls.{field}[{i}]"), err);
+            }
+        }
+    }
+    if let (Some(min), Some(max)) = (pulls.min_changes, pulls.max_changes) {
+        if min > max {
+            report.error(
+                format!("{prefix}pulls.min_changes"),
+                "not allowed above max_changes",
+            );
+        }
+    }
+}
+
+fn repos(report: &mut Report, path: &str, repos: &[String]) {
+    for (i, repo) in repos.iter().enumerate() {
+        if repo.is_empty() {
+            report.error(format!("{path}[{i}]"), "not allowed empty");
+        } else if let Err(err) = Pattern::parse(repo.strip_prefix('!').unwrap_or(repo)) {
+            report.error(format!("{path}[{i}]"), err);
+        } else if repos[..i].contains(repo) {
+            report.warning(format!("{path}[{i}]"), format!("{repo} is listed twice"));
+        }
+    }
+}
+
+// Any body will do, the status tells whether it was found.
+type Found = IgnoredAny;
+
+/// Runs [`validate`], then checks against the forge that the token works and every repo exists.
+pub async fn validate_online(conf: &ConfigData) -> Report {
+    let mut report = validate(conf);
+    if !report.is_valid() {
+        return report;
+    }
+    match client::build(&conf.http) {
+        Ok(local) => client::scoped(local, accounts_online(&mut report, conf)).await,
+        Err(err) => report.error("http", err.to_string()),
+    }
+    report
+}
+
+async fn accounts_online(report: &mut Report, conf: &ConfigData) {
+    if conf.has_primary() {
+        account_online(report, "", &conf.account).await;
+    }
+    for (i, extra) in conf.accounts.iter().enumerate() {
+        account_online(report, &format!("accounts[{i}]."), extra).await;
+    }
+}
+
+async fn account_online(report: &mut Report, prefix: &str, account: &Account) {
+    let api = match get_api(account, "".to_string()) {
+        Ok(api) => api,
+        Err(_) => return,
+    };
+    if let Err(err) = get_client::<Found>(api.user(), api.headers(account.token.as_str())).await {
+        report.error(format!("{prefix}token"), err.to_string());
+        return;
+    }
+
+    let mut targets = vec![(
+        format!("{prefix}owners.repos"),
+        &account.owners.name,
+        &account.owners.repos,
+    )];
+    targets.extend(
+        account
+            .orgs
+            .iter()
+            .map(|(org, repos)| (format!("{prefix}orgs.{org}"), org, repos)),
+    );
+    for (path, owner, repos) in targets {
+        let Ok(api) = get_api(account, owner.clone()) else {
+            continue;
+        };
+        for (i, repo) in repos.iter().enumerate() {
+            // Patterns are resolved against the forge's listing when polling.
+            if !matches!(Pattern::parse(repo), Ok(Pattern::Exact(_))) || repo.starts_with('!') {
+                continue;
+            }
+            let result =
+                get_client::<Found>(api.repo(repo), api.headers(account.token.as_str())).await;
+            if result.is_err() {
+                report.error(
+                    format!("{path}[{i}]"),
+                    format!("{owner}/{repo} not found or not accessible with this token"),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::conf::config::{ConfigData, Owner};
+    use crate::conf::validate::{validate, Severity};
+
+    fn conf() -> ConfigData {
+        let mut conf = ConfigData::new_owner(
+            "github",
+            "ghp_xxx",
+            Owner {
+                name: "baerwang".to_string(),
+                repos: vec!["flexible".to_string()],
+            },
+        );
+        conf.account.reviews = vec!["baerwang".to_string()];
+        conf.dispatch = 1800;
+        conf
+    }
+
+    fn paths(conf: &ConfigData) -> Vec<(String, Severity)> {
+        validate(conf)
+            .issues
+            .into_iter()
+            .map(|issue| (issue.path, issue.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert!(validate(&conf()).issues.is_empty());
+        assert!(conf().valid().is_ok());
+    }
+
+    #[test]
+    fn test_all_issues() {
+        let mut conf = conf();
+        conf.account.token = "".to_string();
+        conf.dispatch = 60;
+        conf.account.owners.repos.push("".to_string());
+        conf.account.orgs = HashMap::from([(
+            "apache".to_string(),
+            vec!["a".to_string(), "b".to_string(), "".to_string()],
+        )]);
+
+        assert_eq!(
+            paths(&conf),
+            vec![
+                ("token".to_string(), Severity::Error),
+                ("owners.repos[1]".to_string(), Severity::Error),
+                ("orgs.apache[2]".to_string(), Severity::Error),
+                ("dispatch".to_string(), Severity::Warning),
+            ]
+        );
+        assert_eq!(
+            conf.valid().unwrap_err().to_string(),
+            "token: not allowed empty"
+        );
+    }
+
+    #[test]
+    fn test_owner_or_orgs() {
+        let mut conf = conf();
+        conf.account.owners = Owner {
+            name: "".to_string(),
+            repos: vec![],
+        };
+        assert_eq!(paths(&conf), vec![("owners".to_string(), Severity::Error)]);
+    }
+
+    #[test]
+    fn test_accounts() {
+        let mut conf = conf();
+        let mut work: ConfigData = serde_json::from_str(
+            r#"{
+                "accounts": [{
+                    "plugin": "github",
+                    "base_url": "https://github.example.com",
+                    "token": "ghp_yyy",
+                    "reviews": ["baerwang"],
+                    "orgs": {"infra": ["deploy"]}
+                }],
+                "dispatch": 1800
+            }"#,
+        )
+        .unwrap();
+        assert!(validate(&work).issues.is_empty());
+        assert_eq!(work.accounts().count(), 1);
+
+        conf.accounts = std::mem::take(&mut work.accounts);
+        conf.accounts[0].orgs.insert("ops".to_string(), vec![]);
+        assert_eq!(
+            paths(&conf),
+            vec![
+                ("accounts[0].orgs.ops".to_string(), Severity::Error),
+                ("accounts[0].name".to_string(), Severity::Error),
+            ]
+        );
+        conf.accounts[0].name = "work".to_string();
+        assert_eq!(conf.accounts().count(), 2);
+    }
+}
@@ -15,43 +15,184 @@
  * limitations under the License.
  */
 
-use crate::conf::config::ConfigData;
+use crate::conf::config::{self, ConfigData};
+use crate::conf::secret;
+use crate::conf::validate::{self, Report};
 use crate::console::model::{Org, Repo};
 use crate::console::Rest;
 use crate::dispatch;
-use crate::plugins::{get_api, get_client};
+use crate::error::Error;
+use crate::logging;
+use crate::plugins::oauth::{self, DeviceCode, Endpoints};
+use crate::plugins::{client, get_api};
+
+// The window edits part of the top-level account only, keep the rest from the saved file.
+fn merge_saved(conf: &mut ConfigData, saved: ConfigData) {
+    conf.accounts = saved.accounts;
+    conf.http = saved.http;
+    let (account, saved) = (&mut conf.account, saved.account);
+    account.base_url = account.base_url.take().or(saved.base_url);
+    account.filter = saved.filter;
+    account.pulls = saved.pulls;
+    account.issues = saved.issues;
+    account.authored = saved.authored;
+    account.ci = saved.ci;
+    account.conflicts = saved.conflicts;
+    account.lifecycle = saved.lifecycle;
+    account.oauth = saved.oauth;
+}
+
+// The window's config over the saved one, e.g. for the oauth section it has no fields for.
+fn with_saved(mut conf: ConfigData) -> ConfigData {
+    if let Ok(saved) = ConfigData::load(&config::default_path()) {
+        merge_saved(&mut conf, saved);
+    }
+    conf
+}
 
 #[tauri::command]
-pub async fn create(conf: ConfigData) -> String {
-    match conf.valid() {
-        "" => {
-            _ = dispatch::execute(conf).await;
-            "".to_string()
-        }
-        r => r.to_string(),
+#[tracing::instrument(skip_all)]
+pub async fn create(mut conf: ConfigData) -> Result<(), Error> {
+    let path = config::default_path();
+    if let Ok(saved) = ConfigData::load(&path) {
+        merge_saved(&mut conf, saved);
     }
+    oauth::resolve_tokens(&mut conf).await?;
+    conf.valid()?;
+    conf.save(&path)
+        .map_err(|err| Error::Config(format!("saving config: {err}")))?;
+    dispatch::execute(conf).await?;
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn repos(conf: ConfigData) -> Rest<Vec<Repo>> {
-    let api = get_api(conf.plugin.as_str(), conf.owners.name.clone(), None);
-    Rest::from_result(get_client::<Vec<Repo>>(api.repos(), api.headers(conf.token.as_str())).await)
+#[tracing::instrument(skip_all)]
+pub async fn validate_config(mut conf: ConfigData, online: bool) -> Report {
+    if let Err(err) = oauth::resolve_tokens(&mut conf).await {
+        tracing::warn!("reading the stored token: {}", err);
+    }
+    if online {
+        validate::validate_online(&conf).await
+    } else {
+        validate::validate(&conf)
+    }
 }
 
 #[tauri::command]
-pub async fn orgs(conf: ConfigData) -> Rest<Vec<Org>> {
-    let api = get_api(conf.plugin.as_str(), "".to_string(), None);
-    Rest::from_result(get_client::<Vec<Org>>(api.orgs(), api.headers(conf.token.as_str())).await)
+#[tracing::instrument(skip_all)]
+pub async fn repos(mut conf: ConfigData) -> Rest<Vec<Repo>> {
+    Rest::from_result(
+        async {
+            let local = client::build(&conf.http)?;
+            client::scoped(local, async {
+                oauth::resolve_tokens(&mut conf).await?;
+                let account = &conf.account;
+                let api = get_api(account, account.owners.name.clone())?;
+                api.list_repos(&account.token, false).await
+            })
+            .await
+        }
+        .await,
+    )
 }
 
 #[tauri::command]
-pub async fn org_repos(conf: ConfigData) -> Rest<Vec<Repo>> {
-    let api = get_api(conf.plugin.as_str(), conf.owners.name.clone(), None);
+#[tracing::instrument(skip_all)]
+pub async fn orgs(mut conf: ConfigData) -> Rest<Vec<Org>> {
     Rest::from_result(
-        get_client::<Vec<Repo>>(api.org_repos(), api.headers(conf.token.as_str())).await,
+        async {
+            let local = client::build(&conf.http)?;
+            client::scoped(local, async {
+                oauth::resolve_tokens(&mut conf).await?;
+                let account = &conf.account;
+                let api = get_api(account, "".to_string())?;
+                api.list_orgs(&account.token).await
+            })
+            .await
+        }
+        .await,
     )
 }
 
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn org_repos(mut conf: ConfigData) -> Rest<Vec<Repo>> {
+    Rest::from_result(
+        async {
+            let local = client::build(&conf.http)?;
+            client::scoped(local, async {
+                oauth::resolve_tokens(&mut conf).await?;
+                let account = &conf.account;
+                let api = get_api(account, account.owners.name.clone())?;
+                api.list_repos(&account.token, true).await
+            })
+            .await
+        }
+        .await,
+    )
+}
+
+/// Every open PR on the watched repos and whether the filters let it through.
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn preview_pulls(conf: ConfigData) -> Rest<dispatch::Previews> {
+    Rest::from_result(
+        async {
+            let mut conf = with_saved(conf);
+            oauth::resolve_tokens(&mut conf).await?;
+            dispatch::preview(conf).await
+        }
+        .await,
+    )
+}
+
+/// Fails with the reason when the account cannot sign in, the window disables Sign in then.
+#[tauri::command]
+pub fn login_available(conf: ConfigData) -> Result<(), Error> {
+    Endpoints::resolve(&with_saved(conf).account).map(|_| ())
+}
+
+/// Starts a device-flow sign in, the window shows the returned code to the user.
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn start_login(conf: ConfigData) -> Result<DeviceCode, Error> {
+    let conf = with_saved(conf);
+    let endpoints = Endpoints::resolve(&conf.account)?;
+    client::scoped(client::build(&conf.http)?, oauth::start(&endpoints)).await
+}
+
+/// Waits for the user to approve `code` and stores the token in the secret store.
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn finish_login(conf: ConfigData, code: DeviceCode) -> Result<(), Error> {
+    let conf = with_saved(conf);
+    let endpoints = Endpoints::resolve(&conf.account)?;
+    let credential =
+        client::scoped(client::build(&conf.http)?, oauth::poll(&endpoints, &code)).await?;
+    secret::store(conf.account.name(), &credential).await
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn logout(conf: ConfigData) -> Result<(), Error> {
+    secret::delete(conf.account.name()).await
+}
+
+#[tauri::command]
+pub fn watch_status() -> dispatch::Status {
+    dispatch::status::current()
+}
+
+#[tauri::command]
+pub fn get_logs(limit: Option<usize>) -> Vec<String> {
+    logging::lines(limit.unwrap_or(usize::MAX))
+}
+
+#[tauri::command]
+pub fn set_log_level(level: String) -> Result<(), Error> {
+    logging::set_level(level.as_str())
+}
+
 #[cfg(test)]
 mod test {
     use std::env;
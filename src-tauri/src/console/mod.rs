@@ -15,26 +15,28 @@
  * limitations under the License.
  */
 
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+
+use crate::error::Error;
 
 pub mod api;
-mod model;
+pub mod model;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct Rest<T> {
     data: Option<T>,
-    error: Option<String>,
+    error: Option<Error>,
 }
 
 impl<T> Rest<T> {
-    pub fn new(data: Option<T>, error: Option<String>) -> Rest<T> {
+    pub fn new(data: Option<T>, error: Option<Error>) -> Rest<T> {
         Rest { data, error }
     }
 
-    pub fn from_result(result: Result<T, reqwest::Error>) -> Rest<T> {
+    pub fn from_result<E: Into<Error>>(result: Result<T, E>) -> Rest<T> {
         match result {
             Ok(data) => Rest::new(Some(data), None),
-            Err(err) => Rest::new(None, Some(err.to_string())),
+            Err(err) => Rest::new(None, Some(err.into())),
         }
     }
 }
@@ -20,9 +20,31 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct Repo {
-    name: String,
+    pub name: String,
     html_url: String,
     updated_at: String,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub fork: bool,
+}
+
+impl Repo {
+    pub fn new(
+        name: String,
+        html_url: String,
+        updated_at: String,
+        archived: bool,
+        fork: bool,
+    ) -> Self {
+        Repo {
+            name,
+            html_url,
+            updated_at,
+            archived,
+            fork,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,3 +52,9 @@ pub struct Repo {
 pub struct Org {
     login: String,
 }
+
+impl Org {
+    pub fn new(login: String) -> Self {
+        Org { login }
+    }
+}
@@ -0,0 +1,58 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Consecutive failures after which a target is no longer polled every tick.
+pub const THRESHOLD: u32 = 3;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+pub enum Transition {
+    Tripped(String, Error),
+    Recovered(String),
+}
+
+/// How long to wait before polling a target again after `failures` consecutive failures.
+///
+/// Below the threshold the target stays on the normal schedule. Once the breaker trips the wait
+/// doubles with every failed retry, starting at one dispatch interval.
+pub fn backoff(failures: u32, every: Duration) -> Option<Duration> {
+    if failures < THRESHOLD {
+        return None;
+    }
+    let exp = (failures - THRESHOLD).min(16);
+    Some(every.saturating_mul(1 << exp).min(MAX_BACKOFF))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::dispatch::breaker::backoff;
+
+    #[test]
+    fn test_backoff() {
+        let every = Duration::from_secs(600);
+        assert_eq!(backoff(2, every), None);
+        assert_eq!(backoff(3, every), Some(every));
+        assert_eq!(backoff(5, every), Some(every * 4));
+        assert_eq!(backoff(40, every), Some(Duration::from_secs(6 * 60 * 60)));
+    }
+}
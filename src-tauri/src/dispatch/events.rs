@@ -0,0 +1,420 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::plugins::api::{CiState, Event, Lifecycle, OpenPull, PullState, Review, ReviewState};
+
+// Event keys already seen per target, a target is absent until its first listing.
+static SEEN: Mutex<BTreeMap<String, BTreeSet<String>>> = Mutex::new(BTreeMap::new());
+
+// Tracked PRs per target as of the previous tick.
+static PULLS: Mutex<BTreeMap<String, BTreeMap<i64, PullState>>> = Mutex::new(BTreeMap::new());
+
+// Open and closed PRs per target as of the previous tick, a target is absent until listed.
+static OPEN: Mutex<BTreeMap<String, Listing>> = Mutex::new(BTreeMap::new());
+
+/// Closed PRs remembered per target to tell a reopen from a new PR, lower numbers go first.
+const CLOSED: usize = 500;
+
+#[derive(Default)]
+struct Listing {
+    open: BTreeMap<i64, OpenPull>,
+    closed: BTreeSet<i64>,
+}
+
+/// Forgets what was seen, e.g. because the filters changed.
+pub fn clear() {
+    SEEN.lock().unwrap().clear();
+    PULLS.lock().unwrap().clear();
+    OPEN.lock().unwrap().clear();
+}
+
+/// The `events` on `target` not seen before. The first listing of a target only records what
+/// is already there, so starting the watcher does not alert on every old issue. Keys missing
+/// from the listing are forgotten so the set does not grow.
+pub fn fresh(target: &str, events: &[Event]) -> Vec<Event> {
+    let mut seen = SEEN.lock().unwrap();
+    let primed = seen.contains_key(target);
+    let keys = seen.entry(target.to_string()).or_default();
+    let mut current = BTreeSet::new();
+    let fresh = events
+        .iter()
+        .filter(|event| current.insert(event.key.clone()) && primed && !keys.contains(&event.key))
+        .cloned()
+        .collect();
+    *keys = current;
+    fresh
+}
+
+/// Pairs each of `pulls` with its state at the previous tick on `target`, then remembers
+/// `pulls` instead. PRs seen for the first time have nothing to compare with and are left out.
+pub fn track(target: &str, pulls: Vec<PullState>) -> Vec<(PullState, PullState)> {
+    let mut tracked = PULLS.lock().unwrap();
+    let previous = tracked.entry(target.to_string()).or_default();
+    let mut current = BTreeMap::new();
+    let mut pairs = Vec::new();
+    for mut pull in pulls {
+        if let Some(old) = previous.remove(&pull.number) {
+            // Keep the last known mergeability while the forge recomputes it.
+            if pull.mergeable.is_none() {
+                pull.mergeable = old.mergeable.clone();
+            }
+            if pull.behind.is_none() {
+                pull.behind = old.behind;
+            }
+            pairs.push((old, pull.clone()));
+        }
+        current.insert(pull.number, pull);
+    }
+    *previous = current;
+    pairs
+}
+
+/// Diffs the `open` PRs on `target` against the previous listing. PRs that left the list are
+/// returned as [`Change::Closed`] with their last known state. The first listing only primes.
+pub fn lifecycle(target: &str, open: Vec<OpenPull>) -> Vec<(OpenPull, Change)> {
+    let mut listings = OPEN.lock().unwrap();
+    let primed = listings.contains_key(target);
+    let listing = listings.entry(target.to_string()).or_default();
+    let mut current = BTreeMap::new();
+    let mut changes = Vec::new();
+    for pull in open {
+        match listing.open.remove(&pull.number) {
+            Some(old) => {
+                if old.draft && !pull.draft {
+                    changes.push((pull.clone(), Change::ReadyForReview));
+                }
+                if old.title != pull.title {
+                    changes.push((pull.clone(), Change::TitleChanged(old.title.clone())));
+                }
+                for user in pull.reviewers.iter().filter(|u| !old.reviewers.contains(u)) {
+                    changes.push((pull.clone(), Change::ReviewerAdded(user.clone())));
+                }
+                for user in old.reviewers.iter().filter(|u| !pull.reviewers.contains(u)) {
+                    changes.push((pull.clone(), Change::ReviewerRemoved(user.clone())));
+                }
+            }
+            None if listing.closed.remove(&pull.number) => {
+                changes.push((pull.clone(), Change::Reopened))
+            }
+            None if primed => changes.push((pull.clone(), Change::Opened)),
+            None => {}
+        }
+        current.insert(pull.number, pull);
+    }
+    for (number, pull) in std::mem::replace(&mut listing.open, current) {
+        listing.closed.insert(number);
+        changes.push((pull, Change::Closed));
+    }
+    while listing.closed.len() > CLOSED {
+        listing.closed.pop_first();
+    }
+    changes
+}
+
+/// Something that happened to a PR between two ticks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Reviewed(Review),
+    /// Commented in the PR conversation.
+    Commented(String),
+    ReadyToMerge,
+    Ci(CiState),
+    ConflictAppeared,
+    ConflictResolved,
+    /// Fell this many commits behind the base branch.
+    Behind(u64),
+    Opened,
+    /// Left the open list, [`Change::Merged`] once the forge confirms it was merged.
+    Closed,
+    Merged,
+    Reopened,
+    ReadyForReview,
+    /// Renamed from the given title.
+    TitleChanged(String),
+    ReviewerAdded(String),
+    ReviewerRemoved(String),
+}
+
+impl Change {
+    /// The lifecycle event this is, `None` for changes of tracked PRs.
+    pub fn lifecycle(&self) -> Option<Lifecycle> {
+        match self {
+            Change::Opened => Some(Lifecycle::Opened),
+            Change::Closed => Some(Lifecycle::Closed),
+            Change::Merged => Some(Lifecycle::Merged),
+            Change::Reopened => Some(Lifecycle::Reopened),
+            Change::ReadyForReview => Some(Lifecycle::ReadyForReview),
+            Change::TitleChanged(_) => Some(Lifecycle::TitleChanged),
+            Change::ReviewerAdded(_) => Some(Lifecycle::ReviewerAdded),
+            Change::ReviewerRemoved(_) => Some(Lifecycle::ReviewerRemoved),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Reviewed(review) => {
+                let action = match review.state {
+                    ReviewState::Approved => "approved",
+                    ReviewState::ChangesRequested => "requested changes",
+                    ReviewState::Commented => "commented",
+                    ReviewState::Dismissed => "dismissed their review",
+                };
+                write!(f, "{} {action}", review.user)
+            }
+            Change::Commented(user) => write!(f, "{user} commented"),
+            Change::ReadyToMerge => write!(f, "ready to merge"),
+            Change::Ci(CiState::Success) => write!(f, "CI passed"),
+            Change::Ci(CiState::Failure) => write!(f, "CI failed"),
+            Change::Ci(CiState::Pending) => write!(f, "CI running"),
+            Change::ConflictAppeared => write!(f, "conflict appeared"),
+            Change::ConflictResolved => write!(f, "conflict resolved"),
+            Change::Behind(commits) => write!(f, "{commits} commits behind base"),
+            Change::Opened => write!(f, "opened"),
+            Change::Closed => write!(f, "closed"),
+            Change::Merged => write!(f, "merged"),
+            Change::Reopened => write!(f, "reopened"),
+            Change::ReadyForReview => write!(f, "ready for review"),
+            Change::TitleChanged(old) => write!(f, "renamed from \"{old}\""),
+            Change::ReviewerAdded(user) => write!(f, "review requested from {user}"),
+            Change::ReviewerRemoved(user) => write!(f, "review request for {user} removed"),
+        }
+    }
+}
+
+/// What changed from `old` to `new`, a PR `behind` or more commits behind its base is stale.
+pub fn changes(old: &PullState, new: &PullState, behind: u64) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (id, review) in &new.reviews {
+        // Authors answering in their own PR show up as reviews too.
+        if old.reviews.contains_key(id)
+            || review.user.eq_ignore_ascii_case(&new.author)
+            || review.state == ReviewState::Dismissed
+        {
+            continue;
+        }
+        changes.push(Change::Reviewed(review.clone()));
+    }
+    for (id, user) in &new.comments {
+        if !old.comments.contains_key(id) && !user.eq_ignore_ascii_case(&new.author) {
+            changes.push(Change::Commented(user.clone()));
+        }
+    }
+    if new.mergeable.as_deref() == Some("clean")
+        && old.mergeable.as_ref().is_some_and(|state| state != "clean")
+    {
+        changes.push(Change::ReadyToMerge);
+    }
+    let dirty = |pull: &PullState| pull.mergeable.as_deref() == Some("dirty");
+    if old.mergeable.is_some() && new.mergeable.is_some() && dirty(old) != dirty(new) {
+        changes.push(if dirty(new) {
+            Change::ConflictAppeared
+        } else {
+            Change::ConflictResolved
+        });
+    }
+    if let (Some(before), Some(after)) = (old.behind, new.behind) {
+        if behind > 0 && before < behind && after >= behind {
+            changes.push(Change::Behind(after));
+        }
+    }
+    if let (Some(before), Some(after)) = (old.ci, new.ci) {
+        if before != after {
+            changes.push(Change::Ci(after));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::dispatch::events::{changes, fresh, lifecycle, track, Change};
+    use crate::plugins::api::{CiState, Event, OpenPull, PullState, Review, ReviewState};
+
+    fn event(key: &str) -> Event {
+        Event {
+            account: "github".to_string(),
+            repo: "flexible".to_string(),
+            number: 1,
+            title: "Crash".to_string(),
+            link: "https://github.com/baerwang/flexible/issues/1".to_string(),
+            reason: "labeled P0".to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fresh() {
+        let target = "github:baerwang/flexible";
+        assert!(fresh(target, &[event("1:labeled P0")]).is_empty());
+        assert!(fresh(target, &[event("1:labeled P0")]).is_empty());
+        let new = fresh(target, &[event("1:labeled P0"), event("2:comment:9")]);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].key, "2:comment:9");
+        assert_eq!(
+            new[0].render("{reason} in {repo}"),
+            "labeled P0 in flexible"
+        );
+        assert!(fresh(target, &[event("2:comment:9")]).is_empty());
+        assert_eq!(fresh(target, &[event("1:labeled P0")]).len(), 1);
+    }
+
+    fn pull(reviews: &[(u64, &str, ReviewState)], mergeable: Option<&str>) -> PullState {
+        PullState {
+            number: 7,
+            author: "baerwang".to_string(),
+            reviews: reviews
+                .iter()
+                .map(|(id, user, state)| {
+                    let user = user.to_string();
+                    (
+                        *id,
+                        Review {
+                            user,
+                            state: *state,
+                        },
+                    )
+                })
+                .collect::<BTreeMap<_, _>>(),
+            mergeable: mergeable.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_review_changes() {
+        let target = "github:baerwang/flexible#authored";
+        assert!(track(target, vec![pull(&[], Some("blocked"))]).is_empty());
+
+        let pairs = track(
+            target,
+            vec![pull(
+                &[
+                    (1, "alice", ReviewState::Approved),
+                    (2, "baerwang", ReviewState::Commented),
+                ],
+                None,
+            )],
+        );
+        assert_eq!(pairs.len(), 1);
+        let (old, new) = &pairs[0];
+        assert_eq!(new.mergeable.as_deref(), Some("blocked"));
+        assert_eq!(
+            changes(old, new, 0)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["alice approved"]
+        );
+
+        let mut ready = pull(&[(1, "alice", ReviewState::Approved)], Some("clean"));
+        ready.ci = Some(CiState::Pending);
+        let pairs = track(target, vec![ready.clone()]);
+        assert_eq!(
+            changes(&pairs[0].0, &pairs[0].1, 0),
+            vec![Change::ReadyToMerge]
+        );
+
+        ready.ci = Some(CiState::Failure);
+        let pairs = track(target, vec![ready]);
+        assert_eq!(
+            changes(&pairs[0].0, &pairs[0].1, 0),
+            vec![Change::Ci(CiState::Failure)]
+        );
+
+        let mut commented = pull(&[], None);
+        commented.comments = BTreeMap::from([(8, "bob".to_string()), (9, "baerwang".to_string())]);
+        assert_eq!(
+            changes(&pull(&[], None), &commented, 0),
+            vec![Change::Commented("bob".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_conflict_changes() {
+        let mut old = pull(&[], Some("clean"));
+        old.behind = Some(3);
+        let mut new = pull(&[], Some("dirty"));
+        new.behind = Some(12);
+        assert_eq!(
+            changes(&old, &new, 10),
+            vec![Change::ConflictAppeared, Change::Behind(12)]
+        );
+        assert_eq!(changes(&new, &new, 10), vec![]);
+        assert_eq!(
+            changes(&new, &old, 10)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["ready to merge", "conflict resolved"]
+        );
+    }
+
+    fn open(number: i64, title: &str, draft: bool, reviewers: &[&str]) -> OpenPull {
+        OpenPull {
+            number,
+            title: title.to_string(),
+            draft,
+            reviewers: reviewers.iter().map(|user| user.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let target = "github:baerwang/flexible#lifecycle";
+        let reasons = |open| {
+            lifecycle(target, open)
+                .into_iter()
+                .map(|(pull, change)| format!("#{} {change}", pull.number))
+                .collect::<Vec<_>>()
+        };
+        assert!(reasons(vec![open(1, "Fix", true, &["alice"])]).is_empty());
+        assert_eq!(
+            reasons(vec![
+                open(1, "Fix crash", false, &["bob"]),
+                open(2, "Docs", false, &[])
+            ]),
+            vec![
+                "#1 ready for review",
+                "#1 renamed from \"Fix\"",
+                "#1 review requested from bob",
+                "#1 review request for alice removed",
+                "#2 opened",
+            ]
+        );
+        assert_eq!(
+            reasons(vec![open(2, "Docs", false, &[])]),
+            vec!["#1 closed"]
+        );
+        assert_eq!(
+            reasons(vec![
+                open(1, "Fix crash", false, &["bob"]),
+                open(2, "Docs", false, &[])
+            ]),
+            vec!["#1 reopened"]
+        );
+    }
+}
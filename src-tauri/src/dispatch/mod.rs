@@ -15,64 +15,425 @@
  * limitations under the License.
  */
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+use serde::Serialize;
+use tokio::sync::{Mutex, RwLock};
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::Instrument;
 
-use crate::conf::config::ConfigData;
-use crate::plugins::github::GitHub;
+use crate::conf::config::{Account, ConfigData};
+use crate::dispatch::breaker::Transition;
+use crate::dispatch::events::Change;
+use crate::error::Error;
+use crate::notification::notify::{alert, notify_event};
+use crate::plugins::api::{Api, Event, Lifecycle, PullRequest, ReviewRequest};
+use crate::plugins::{client, get_api, oauth};
+use crate::util;
 
-pub async fn execute(c: ConfigData) -> Result<String, JobSchedulerError> {
+pub mod breaker;
+pub mod events;
+pub mod output;
+pub mod resolve;
+pub mod status;
+
+pub use status::{is_paused, pause, subscribe, Status};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Check {
+    pub reviews: Vec<ReviewRequest>,
+    pub events: Vec<Event>,
+    pub error: Option<Error>,
+    /// Every polled `owner/repo`, with the error it failed with.
+    pub repos: BTreeMap<String, Option<Error>>,
+    /// Every `owner/repo` whose issues could not be watched, apart from its PR health.
+    pub issues: BTreeMap<String, Error>,
+}
+
+static SCHEDULER: Mutex<Option<JobScheduler>> = Mutex::const_new(None);
+
+// Every running tick holds a read guard, so taking the write guard waits for all of them.
+static RUNNING: RwLock<()> = RwLock::const_new(());
+
+pub async fn execute(c: ConfigData) -> Result<String, Error> {
+    let mut scheduler = SCHEDULER.lock().await;
+    if let Some(mut previous) = scheduler.take() {
+        previous.shutdown().await?;
+    }
+
+    client::configure(&c.http)?;
+    resolve::clear();
+    events::clear();
+    status::clear();
+    tracing::info!(every = c.dispatch, "starting scheduler");
     let sched = JobScheduler::new().await?;
+    let every = Duration::from_secs(c.dispatch);
     let c_shared = Arc::new(c);
 
-    let task = Job::new_repeated(Duration::from_secs(c_shared.dispatch), move |_uuid, _l| {
+    let task = Job::new_repeated(every, move |_uuid, _l| {
+        if is_paused() {
+            return;
+        }
         let c_shared = Arc::clone(&c_shared);
         tokio::spawn(async move {
-            execute_workflow(c_shared).await;
+            let _running = RUNNING.read().await;
+            let (started, instant) = (util::now(), Instant::now());
+            let check = execute_workflow(c_shared, true)
+                .instrument(tracing::info_span!("tick"))
+                .await;
+            // Alert once when the token breaks, not on every tick it stays broken.
+            if let Some(err @ Error::Auth(_)) = &check.error {
+                if status::current().error.as_ref() != Some(err) {
+                    alert("flexible token needs attention", &err.to_string());
+                }
+            }
+            for transition in status::record(check, started, instant.elapsed(), every) {
+                match transition {
+                    Transition::Tripped(_, Error::Auth(_)) => {}
+                    Transition::Tripped(target, err) => {
+                        alert(&format!("{target} unreachable"), &err.to_string())
+                    }
+                    Transition::Recovered(target) => {
+                        alert(&format!("{target} reachable again"), "Polling resumed")
+                    }
+                }
+            }
         });
     })?;
 
     let uuid = sched.add(task).await?;
     sched.start().await?;
+    *scheduler = Some(sched);
+    status::scheduled(Some(every));
     Ok(uuid.to_string())
 }
 
-async fn execute_workflow(c_shared: Arc<ConfigData>) {
-    // Execute tasks for owners' repos
-    if !c_shared.owners.name.is_empty() {
-        let hub = GitHub::new(c_shared.owners.name.clone(), c_shared.reviews());
+/// Runs the workflow once and returns the pending reviews without notifying or publishing them.
+pub async fn check(c: ConfigData) -> Check {
+    match client::build(&c.http) {
+        Ok(local) => client::scoped(local, execute_workflow(Arc::new(c), false)).await,
+        Err(err) => Check {
+            error: Some(err),
+            ..Default::default()
+        },
+    }
+}
+
+/// Stops the scheduler and waits up to `deadline` for ticks that are still running.
+pub async fn shutdown(deadline: Duration) {
+    if let Some(mut sched) = SCHEDULER.lock().await.take() {
+        if let Err(err) = sched.shutdown().await {
+            tracing::error!("Error stopping scheduler: {}", err);
+        }
+        status::scheduled(None);
+    }
+    if tokio::time::timeout(deadline, RUNNING.write())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Running ticks did not finish within {:?}", deadline);
+    }
+}
+
+/// An open PR on a watched repo and why it would not be notified, `None` when it would.
+#[derive(Debug, Clone, Serialize)]
+pub struct Preview {
+    #[serde(flatten)]
+    pub review: ReviewRequest,
+    pub rejected: Option<String>,
+}
+
+/// The previewed PRs, and the error each `owner/repo` that could not be previewed failed with.
+#[derive(Debug, Default, Serialize)]
+pub struct Previews {
+    pub pulls: Vec<Preview>,
+    pub errors: BTreeMap<String, Error>,
+}
+
+/// Evaluates the PR filters on every watched repo without notifying.
+pub async fn preview(c: ConfigData) -> Result<Previews, Error> {
+    Ok(client::scoped(client::build(&c.http)?, preview_accounts(&c)).await)
+}
+
+async fn preview_accounts(c: &ConfigData) -> Previews {
+    let mut previews = Previews::default();
+    for account in c.accounts() {
+        let token = match oauth::token(account).await {
+            Ok(token) => token,
+            Err(err) => {
+                previews.errors.insert(account.name().to_string(), err);
+                continue;
+            }
+        };
+        for (owner, entries, org) in watches(account) {
+            let target = account.target(owner, "*");
+            let hub = match get_api(account, owner.clone()) {
+                Ok(hub) => hub,
+                Err(err) => {
+                    previews.errors.insert(target, err);
+                    continue;
+                }
+            };
+            let repos =
+                match resolve::repos(hub.as_ref(), &token, account, owner, entries, org).await {
+                    Ok(repos) => repos,
+                    Err(err) => {
+                        previews.errors.insert(target, err);
+                        continue;
+                    }
+                };
+            for repo in repos {
+                match hub.preview(&token, &repo).await {
+                    Ok(pulls) => previews.pulls.extend(
+                        pulls
+                            .into_iter()
+                            .map(|(review, rejected)| Preview { review, rejected }),
+                    ),
+                    Err(err) => {
+                        previews.errors.insert(account.target(owner, &repo), err);
+                    }
+                }
+            }
+        }
+    }
+    previews
+}
+
+async fn execute_workflow(c_shared: Arc<ConfigData>, scheduled: bool) -> Check {
+    let mut check = Check::default();
+
+    for account in c_shared.accounts() {
+        if let Err(err) = execute_account(account, &mut check, scheduled)
+            .instrument(tracing::info_span!("account", name = account.name()))
+            .await
+        {
+            tracing::warn!(account = account.name(), "Error executing account: {}", err);
+            keep_error(&mut check, err);
+        }
+    }
+
+    tracing::info!(reviews = check.reviews.len(), "tick finished");
+    check
+}
+
+/// The owners and orgs `account` watches, with their repo lists and whether each is an org.
+fn watches(account: &Account) -> Vec<(&String, &Vec<String>, bool)> {
+    let mut watches: Vec<_> = account
+        .orgs
+        .iter()
+        .filter(|(org, repos)| !org.is_empty() && !repos.is_empty())
+        .map(|(org, repos)| (org, repos, true))
+        .collect();
+    // Owners' repos first, then orgs' repos
+    if !account.owners.name.is_empty() {
+        watches.insert(0, (&account.owners.name, &account.owners.repos, false));
+    }
+    watches
+}
+
+async fn execute_account(
+    account: &Account,
+    check: &mut Check,
+    scheduled: bool,
+) -> Result<(), Error> {
+    get_api(account, String::new())?;
+    let token = oauth::token(account).await?;
+
+    for (owner, entries, org) in watches(account) {
+        let hub = get_api(account, owner.clone())?;
+        let repos = match resolve::repos(hub.as_ref(), &token, account, owner, entries, org).await {
+            Ok(repos) => repos,
+            Err(err) => {
+                tracing::warn!(owner, "Error resolving repos: {}", err);
+                keep_error(check, err);
+                continue;
+            }
+        };
         execute_plugin_tasks(
-            Arc::clone(&c_shared),
-            &hub,
-            c_shared.owners.repos.iter().map(|repo| repo.as_str()),
+            account,
+            &token,
+            hub.as_ref(),
+            repos.iter().map(|repo| repo.as_str()),
+            check,
+            scheduled,
         )
         .await;
     }
+    Ok(())
+}
 
-    // Execute tasks for orgs' repos
-    for (org, repos) in &c_shared.orgs {
-        if !org.is_empty() && !repos.is_empty() {
-            let hub = GitHub::new(org.to_string(), c_shared.reviews());
-            execute_plugin_tasks(
-                Arc::clone(&c_shared),
-                &hub,
-                repos.iter().map(|repo| repo.as_str()),
-            )
-            .await;
-        }
+// Keep the first error, unless a later one affects every repo.
+fn keep_error(check: &mut Check, err: Error) {
+    if check
+        .error
+        .as_ref()
+        .is_none_or(|e| !e.is_fatal() && err.is_fatal())
+    {
+        check.error = Some(err);
     }
 }
 
 async fn execute_plugin_tasks(
-    c_shared: Arc<ConfigData>,
-    hub: &GitHub,
+    account: &Account,
+    token: &str,
+    hub: &dyn Api,
     repos: impl Iterator<Item = &str>,
+    check: &mut Check,
+    scheduled: bool,
 ) {
     for repo in repos {
-        if let Err(err) = hub.execute(c_shared.token.as_str(), repo).await {
-            eprintln!("Error executing task: {}", err);
+        let target = account.target(hub.owner(), repo);
+        if scheduled && status::retry_at(&target).is_some_and(|at| at > util::now()) {
+            tracing::debug!(repo = target, "skipping tripped target");
+            continue;
+        }
+        match hub.execute(token, repo).await {
+            Ok(poll) => {
+                check.repos.insert(target.clone(), None);
+                let reviews = poll.reviews;
+                if scheduled {
+                    // Held until CI passes, its CI event notifies the reviewer then.
+                    let held: Vec<i64> = poll
+                        .pulls
+                        .iter()
+                        .filter(|pull| account.ci.holds(pull.ci))
+                        .map(|pull| pull.number)
+                        .collect();
+                    for (old, new) in events::track(&target, poll.pulls) {
+                        for change in events::changes(&old, &new, account.conflicts.behind) {
+                            let (title, body) = match change {
+                                Change::Ci(state) if account.ci.notifies(&new, state) => {
+                                    (&account.ci.title, &account.ci.body)
+                                }
+                                Change::Ci(_) => continue,
+                                Change::ConflictAppeared
+                                | Change::ConflictResolved
+                                | Change::Behind(_)
+                                    if account.conflicts.enabled =>
+                                {
+                                    (&account.conflicts.title, &account.conflicts.body)
+                                }
+                                Change::ConflictAppeared
+                                | Change::ConflictResolved
+                                | Change::Behind(_) => continue,
+                                _ if new.authored => {
+                                    (&account.authored.title, &account.authored.body)
+                                }
+                                _ => continue,
+                            };
+                            let event = Event {
+                                account: account.name().to_string(),
+                                repo: repo.to_string(),
+                                number: new.number,
+                                title: new.title.clone(),
+                                link: new.link.clone(),
+                                reason: change.to_string(),
+                                key: String::new(),
+                            };
+                            notify_event(&event.render(title), &event.render(body));
+                            check.events.push(event);
+                        }
+                    }
+                    reviews
+                        .iter()
+                        .filter(|review| !held.contains(&review.number))
+                        .for_each(|review| {
+                            hub.notify(
+                                repo,
+                                "",
+                                PullRequest {
+                                    title: review.title.clone(),
+                                    number: review.number,
+                                },
+                            )
+                        });
+                }
+                if scheduled && account.lifecycle.enabled {
+                    for (pull, change) in events::lifecycle(&target, poll.open) {
+                        let change = match change {
+                            Change::Closed => match hub.merged(token, repo, pull.number).await {
+                                Ok(true) => Change::Merged,
+                                Ok(false) => Change::Closed,
+                                Err(err) => {
+                                    tracing::warn!(repo, pull.number, "Error reading PR: {}", err);
+                                    Change::Closed
+                                }
+                            },
+                            Change::ReviewerRemoved(user)
+                                if account.lifecycle.notifies(Lifecycle::ReviewerRemoved) =>
+                            {
+                                match hub.reviewed(token, repo, pull.number).await {
+                                    // Reviewing took them off the list, nobody removed them.
+                                    Ok(reviewed) if reviewed.contains(&user) => continue,
+                                    Ok(_) => Change::ReviewerRemoved(user),
+                                    Err(err) => {
+                                        tracing::warn!(
+                                            repo,
+                                            pull.number,
+                                            "Error reading reviews: {}",
+                                            err
+                                        );
+                                        Change::ReviewerRemoved(user)
+                                    }
+                                }
+                            }
+                            change => change,
+                        };
+                        if !change
+                            .lifecycle()
+                            .is_some_and(|l| account.lifecycle.notifies(l))
+                        {
+                            continue;
+                        }
+                        let event = Event {
+                            account: account.name().to_string(),
+                            repo: repo.to_string(),
+                            number: pull.number,
+                            title: pull.title,
+                            link: pull.link,
+                            reason: change.to_string(),
+                            key: String::new(),
+                        };
+                        notify_event(
+                            &event.render(&account.lifecycle.title),
+                            &event.render(&account.lifecycle.body),
+                        );
+                        check.events.push(event);
+                    }
+                }
+                check.reviews.extend(reviews);
+            }
+            Err(err) => {
+                tracing::warn!(owner = hub.owner(), repo, "Error executing task: {}", err);
+                check.repos.insert(target, Some(err.clone()));
+                keep_error(check, err);
+                continue;
+            }
+        }
+
+        if account.issues.enabled {
+            match hub
+                .watch_issues(token, repo, &account.issues, account.issue_users())
+                .await
+            {
+                Ok(events) if scheduled => {
+                    // Ticks publish only what they notified, a check lists every match.
+                    for event in events::fresh(&target, &events) {
+                        notify_event(
+                            &event.render(&account.issues.title),
+                            &event.render(&account.issues.body),
+                        );
+                        check.events.push(event);
+                    }
+                }
+                Ok(events) => check.events.extend(events),
+                Err(err) => {
+                    tracing::warn!(owner = hub.owner(), repo, "Error watching issues: {}", err);
+                    check.issues.insert(target, err);
+                }
+            }
         }
     }
 }
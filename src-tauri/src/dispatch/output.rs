@@ -0,0 +1,113 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+
+use crate::dispatch::Check;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Table,
+    Count,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            "count" => Ok(Format::Count),
+            _ => Err(format!("unknown format {s}, expected json, table or count")),
+        }
+    }
+}
+
+pub fn render(format: Format, check: &Check) -> String {
+    match format {
+        Format::Json => serde_json::to_string_pretty(check).unwrap_or_default(),
+        Format::Table => table(check),
+        Format::Count => check.reviews.len().to_string(),
+    }
+}
+
+fn table(check: &Check) -> String {
+    let rows: Vec<[String; 3]> = check
+        .reviews
+        .iter()
+        .map(|review| {
+            [
+                format!("{}#{}", review.repo, review.number),
+                review.title.clone(),
+                review.link.clone(),
+            ]
+        })
+        .collect();
+    let header = ["PR".to_string(), "TITLE".to_string(), "LINK".to_string()];
+
+    let mut widths = [0; 2];
+    for row in rows.iter().chain(std::iter::once(&header)) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    std::iter::once(&header)
+        .chain(rows.iter())
+        .map(|[pr, title, link]| format!("{pr:<0$}  {title:<1$}  {link}", widths[0], widths[1]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dispatch::output::{render, Format};
+    use crate::dispatch::Check;
+    use crate::plugins::api::ReviewRequest;
+
+    fn check() -> Check {
+        Check {
+            reviews: vec![ReviewRequest {
+                account: "github".to_string(),
+                repo: "flexible".to_string(),
+                number: 7,
+                title: "Add tray".to_string(),
+                link: "https://github.com/baerwang/flexible/pull/7".to_string(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!("json".parse(), Ok(Format::Json));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(Format::Count, &check()), "1");
+        assert_eq!(
+            render(Format::Table, &check()),
+            "PR          TITLE     LINK\n\
+             flexible#7  Add tray  https://github.com/baerwang/flexible/pull/7"
+        );
+        assert!(render(Format::Json, &check()).contains("\"number\": 7"));
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::conf::config::Account;
+use crate::conf::selector::Selection;
+use crate::error::Error;
+use crate::plugins::api::Api;
+use crate::util;
+
+/// How long a resolved repo list is reused before the forge is listed again, in seconds.
+const REFRESH: u64 = 3600;
+
+#[derive(Clone)]
+struct Resolved {
+    at: u64,
+    repos: Vec<String>,
+}
+
+// Keyed by `account:owner/*`.
+static RESOLVED: Mutex<BTreeMap<String, Resolved>> = Mutex::new(BTreeMap::new());
+
+/// Forgets every resolved list, e.g. because the selectors changed.
+pub fn clear() {
+    RESOLVED.lock().unwrap().clear();
+}
+
+/// The repos `entries` select under `owner`, listing them through `api` at most once per [`REFRESH`].
+pub async fn repos(
+    api: &dyn Api,
+    token: &str,
+    account: &Account,
+    owner: &str,
+    entries: &[String],
+    org: bool,
+) -> Result<Vec<String>, Error> {
+    let selection = Selection::parse(entries).map_err(Error::Config)?;
+    if selection.is_static() {
+        return Ok(entries.to_vec());
+    }
+
+    let key = account.target(owner, "*");
+    let now = util::now();
+    let cached = RESOLVED.lock().unwrap().get(&key).cloned();
+    if let Some(resolved) = &cached {
+        if now < resolved.at + REFRESH {
+            return Ok(resolved.repos.clone());
+        }
+    }
+
+    let listed = match api.list_repos(token, org).await {
+        Ok(listed) => listed,
+        Err(err) => {
+            // Keep watching the last known repos until the listing works again.
+            return match cached {
+                Some(resolved) => {
+                    tracing::warn!(owner, "Error listing repos, keeping the last list: {}", err);
+                    Ok(resolved.repos)
+                }
+                None => Err(err),
+            };
+        }
+    };
+    let repos = selection.select(
+        listed
+            .iter()
+            .filter(|repo| account.filter.archived || !repo.archived)
+            .filter(|repo| account.filter.forks || !repo.fork)
+            .map(|repo| repo.name.as_str()),
+    );
+    tracing::info!(owner, repos = repos.len(), "resolved repo selectors");
+    RESOLVED.lock().unwrap().insert(
+        key,
+        Resolved {
+            at: now,
+            repos: repos.clone(),
+        },
+    );
+    Ok(repos)
+}
@@ -0,0 +1,156 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::dispatch::breaker::{self, Transition};
+use crate::dispatch::Check;
+use crate::error::Error;
+use crate::plugins::api::{Event, ReviewRequest};
+use crate::plugins::{self, RateLimit};
+use crate::util::now;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    #[default]
+    Stopped,
+    Running,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepoHealth {
+    pub last_success: Option<u64>,
+    pub last_failure: Option<u64>,
+    pub last_error: Option<Error>,
+    pub consecutive_failures: u32,
+    pub tripped: bool,
+    pub retry_at: Option<u64>,
+}
+
+/// Times are unix seconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Status {
+    pub scheduler: Scheduler,
+    pub paused: bool,
+    pub error: Option<Error>,
+    pub reviews: Vec<ReviewRequest>,
+    pub events: Vec<Event>,
+    pub last_tick: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub next_tick: Option<u64>,
+    pub repos: BTreeMap<String, RepoHealth>,
+    /// Repos whose issues could not be watched at the last tick.
+    pub issues: BTreeMap<String, Error>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+static STATUS: LazyLock<watch::Sender<Status>> =
+    LazyLock::new(|| watch::Sender::new(Status::default()));
+
+/// Dispatcher status, republished after every tick and whenever the watcher is paused or resumed.
+pub fn subscribe() -> watch::Receiver<Status> {
+    STATUS.subscribe()
+}
+
+pub fn current() -> Status {
+    STATUS.borrow().clone()
+}
+
+pub fn pause(paused: bool) {
+    STATUS.send_modify(|status| status.paused = paused);
+}
+
+pub fn is_paused() -> bool {
+    STATUS.borrow().paused
+}
+
+/// When a tripped target may be polled again.
+pub fn retry_at(target: &str) -> Option<u64> {
+    STATUS.borrow().repos.get(target)?.retry_at
+}
+
+/// Forgets the health of every repo, so a new config starts without old failures or backoff.
+pub(crate) fn clear() {
+    STATUS.send_modify(|status| {
+        status.repos.clear();
+        status.issues.clear();
+    });
+}
+
+pub(crate) fn scheduled(every: Option<Duration>) {
+    STATUS.send_modify(|status| match every {
+        Some(every) => {
+            status.scheduler = Scheduler::Running;
+            status.next_tick = Some(now() + every.as_secs());
+        }
+        None => {
+            status.scheduler = Scheduler::Stopped;
+            status.next_tick = None;
+        }
+    });
+}
+
+pub(crate) fn record(
+    check: Check,
+    started: u64,
+    duration: Duration,
+    every: Duration,
+) -> Vec<Transition> {
+    let mut transitions = Vec::new();
+    STATUS.send_modify(|status| {
+        for (target, result) in check.repos {
+            let health = status.repos.entry(target.clone()).or_default();
+            match result {
+                None => {
+                    if health.tripped {
+                        transitions.push(Transition::Recovered(target));
+                    }
+                    health.last_success = Some(started);
+                    health.consecutive_failures = 0;
+                    health.tripped = false;
+                    health.retry_at = None;
+                }
+                Some(err) => {
+                    health.last_failure = Some(started);
+                    health.last_error = Some(err.clone());
+                    health.consecutive_failures += 1;
+                    health.retry_at = breaker::backoff(health.consecutive_failures, every)
+                        .map(|backoff| started + backoff.as_secs());
+                    if !health.tripped && health.retry_at.is_some() {
+                        health.tripped = true;
+                        transitions.push(Transition::Tripped(target, err));
+                    }
+                }
+            }
+        }
+        status.reviews = check.reviews;
+        status.events = check.events;
+        status.issues = check.issues;
+        status.error = check.error;
+        status.last_tick = Some(started);
+        status.last_duration_ms = Some(duration.as_millis() as u64);
+        status.next_tick = Some(started + every.as_secs());
+        status.rate_limit = plugins::rate_limit();
+    });
+    transitions
+}
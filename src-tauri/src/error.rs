@@ -0,0 +1,156 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Config(String),
+    #[error("authentication failed: {0}")]
+    Auth(AuthFailure),
+    #[error("rate limit exceeded: {0}")]
+    RateLimit(String),
+    #[error("network error: {0}")]
+    Network(String),
+    #[error("unexpected response: {0}")]
+    Decode(String),
+    #[error("{0}")]
+    Http(HttpError),
+    #[error("{0}")]
+    Plugin(String),
+    #[error("scheduler error: {0}")]
+    Scheduler(String),
+    #[error("secret store: {0}")]
+    Secret(String),
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Auth(_) => "auth",
+            Error::RateLimit(_) => "rate_limit",
+            Error::Network(_) => "network",
+            Error::Decode(_) => "decode",
+            Error::Http(_) => "http",
+            Error::Plugin(_) => "plugin",
+            Error::Scheduler(_) => "scheduler",
+            Error::Secret(_) => "secret",
+        }
+    }
+
+    /// Auth failures and rate limits affect every repo, unlike a single missing repo.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Auth(_) | Error::RateLimit(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthFailure {
+    /// The token is unknown, expired or revoked.
+    BadCredentials,
+    /// The org enforces SAML SSO and the token was not authorized for it.
+    Sso {
+        org: String,
+        url: String,
+    },
+    /// None of the scopes the endpoint accepts were granted to the token.
+    Scopes {
+        required: Vec<String>,
+        granted: Vec<String>,
+    },
+    Forbidden(String),
+    /// The device login was denied, or its code expired before it was entered.
+    Login(String),
+}
+
+impl fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthFailure::BadCredentials => {
+                write!(f, "the token is invalid or expired, create a new one")
+            }
+            AuthFailure::Sso { org, url } => {
+                write!(f, "authorize the token for org {org} at {url}")
+            }
+            AuthFailure::Scopes { required, granted } => write!(
+                f,
+                "the token needs one of the scopes [{}] but only has [{}]",
+                required.join(", "),
+                granted.join(", ")
+            ),
+            AuthFailure::Forbidden(message) => write!(f, "{message}"),
+            AuthFailure::Login(message) => write!(f, "sign in failed, {message}"),
+        }
+    }
+}
+
+/// A non-success response, with the forge's own explanation when it sent one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpError {
+    pub status: u16,
+    pub url: String,
+    pub message: String,
+    pub documentation_url: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({})", self.status, self.message, self.url)?;
+        if !self.errors.is_empty() {
+            write!(f, ": {}", self.errors.join(", "))?;
+        }
+        if let Some(url) = &self.documentation_url {
+            write!(f, ", see {url}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        let err = err.without_url();
+        match err.status() {
+            Some(StatusCode::UNAUTHORIZED) => Error::Auth(AuthFailure::BadCredentials),
+            Some(StatusCode::FORBIDDEN) => Error::Auth(AuthFailure::Forbidden(err.to_string())),
+            Some(StatusCode::TOO_MANY_REQUESTS) => Error::RateLimit(err.to_string()),
+            _ if err.is_decode() => Error::Decode(err.to_string()),
+            _ => Error::Network(err.to_string()),
+        }
+    }
+}
+
+impl From<tokio_cron_scheduler::JobSchedulerError> for Error {
+    fn from(err: tokio_cron_scheduler::JobSchedulerError) -> Self {
+        Error::Scheduler(err.to_string())
+    }
+}
@@ -18,5 +18,8 @@
 pub mod conf;
 pub mod console;
 pub mod dispatch;
+pub mod error;
+pub mod logging;
 pub mod notification;
 pub mod plugins;
+pub mod util;
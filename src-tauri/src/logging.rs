@@ -0,0 +1,111 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::error::Error;
+
+const MAX_LINES: usize = 1000;
+const MAX_FILES: usize = 7;
+
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static LEVEL: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// Keeps the most recent lines in memory for the log viewer.
+struct Buffer;
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut lines = LINES.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines() {
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Logs to stderr, the in-memory viewer buffer and, when `dir` is set, daily rotated files.
+/// A `dir` that cannot be written only loses the files.
+pub fn init(dir: Option<&Path>) -> Result<(), Error> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let file = dir.map(|dir| {
+        Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("flexible")
+            .filename_suffix("log")
+            .max_log_files(MAX_FILES)
+            .build(dir)
+            .map_err(|err| format!("log dir {}: {err}", dir.display()))
+    });
+    let (file, unwritable) = match file.transpose() {
+        Ok(file) => (file, None),
+        Err(err) => (None, Some(err)),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(io::stderr))
+        .with(fmt::layer().with_ansi(false).with_writer(|| Buffer))
+        .with(file.map(|file| fmt::layer().with_ansi(false).with_writer(file)))
+        .try_init()
+        .map_err(|err| Error::Config(err.to_string()))?;
+
+    if let Some(err) = unwritable {
+        tracing::warn!("Not writing log files, {}", err);
+    }
+    _ = LEVEL.set(handle);
+    Ok(())
+}
+
+/// Changes verbosity at runtime, e.g. `debug` or `flexible=trace,reqwest=info`.
+pub fn set_level(level: &str) -> Result<(), Error> {
+    let filter = EnvFilter::try_new(level)
+        .map_err(|err| Error::Config(format!("invalid log level {level}: {err}")))?;
+    match LEVEL.get() {
+        Some(handle) => handle
+            .reload(filter)
+            .map_err(|err| Error::Config(err.to_string())),
+        None => Err(Error::Config("logging is not initialized".to_string())),
+    }
+}
+
+/// The last `limit` lines, oldest first.
+pub fn lines(limit: usize) -> Vec<String> {
+    let lines = LINES.lock().unwrap();
+    lines
+        .iter()
+        .skip(lines.len().saturating_sub(limit))
+        .cloned()
+        .collect()
+}
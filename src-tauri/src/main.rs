@@ -17,7 +17,8 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use flexible::console;
+use flexible::{console, dispatch, logging};
+use tauri::{AppHandle, Manager};
 
 mod tray;
 
@@ -25,12 +26,38 @@ fn main() {
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             console::api::create,
+            console::api::validate_config,
             console::api::repos,
             console::api::orgs,
-            console::api::org_repos
+            console::api::org_repos,
+            console::api::preview_pulls,
+            console::api::login_available,
+            console::api::start_login,
+            console::api::finish_login,
+            console::api::logout,
+            console::api::watch_status,
+            console::api::get_logs,
+            console::api::set_log_level
         ])
+        .setup(|app| {
+            logging::init(app.path_resolver().app_log_dir().as_deref())?;
+            tray::watch(app.handle());
+            emit_status(app.handle());
+            Ok(())
+        })
         .system_tray(tray::menu())
         .on_system_tray_event(tray::handler)
         .run(tauri::generate_context!())
         .expect("error while running flexible application");
 }
+
+/// Forwards every dispatcher status update to the window as a `watch-status` event.
+fn emit_status(app: AppHandle) {
+    let mut rx = dispatch::subscribe();
+    tauri::async_runtime::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let status = rx.borrow_and_update().clone();
+            _ = app.emit_all("watch-status", status);
+        }
+    });
+}
@@ -17,11 +17,28 @@
 
 use notify_rust::Notification;
 
-pub fn notify(_: &str, title: &str, link: &str) {
+pub fn notify(account: &str, title: &str, link: &str) {
     _ = Notification::new()
-        .summary("New PR review")
+        .summary(format!("New PR review ({account})").as_str())
         .body(format!("{}\n{}", title, link).as_str())
         .appname("flexible")
         .timeout(1)
         .show();
 }
+
+pub fn notify_event(summary: &str, body: &str) {
+    _ = Notification::new()
+        .summary(summary)
+        .body(body)
+        .appname("flexible")
+        .timeout(1)
+        .show();
+}
+
+pub fn alert(summary: &str, body: &str) {
+    _ = Notification::new()
+        .summary(summary)
+        .body(body)
+        .appname("flexible")
+        .show();
+}
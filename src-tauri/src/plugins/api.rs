@@ -15,22 +15,334 @@
  * limitations under the License.
  */
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+use async_trait::async_trait;
 use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::conf::config::Account;
+use crate::conf::filter::{IssueFilter, PullFilter};
+use crate::console::model::{Org, Repo};
+use crate::error::Error;
 
 pub struct PullRequest {
     pub title: String,
     pub number: i64,
 }
 
-pub trait Api: Send {
-    fn api(&self) -> &str;
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewRequest {
+    /// The account whose watch list found it.
+    pub account: String,
+    pub repo: String,
+    pub number: i64,
+    pub title: String,
+    pub link: String,
+}
+
+/// A change on an issue or PR worth a notification.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub account: String,
+    pub repo: String,
+    pub number: i64,
+    pub title: String,
+    pub link: String,
+    pub reason: String,
+    /// Tells events apart across ticks, e.g. `12:assigned to alice` or `12:comment:345`.
+    #[serde(skip)]
+    pub key: String,
+}
+
+impl Event {
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{account}", &self.account)
+            .replace("{repo}", &self.repo)
+            .replace("{number}", &self.number.to_string())
+            .replace("{title}", &self.title)
+            .replace("{link}", &self.link)
+            .replace("{reason}", &self.reason)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReviewState {
+    Approved,
+    ChangesRequested,
+    Commented,
+    Dismissed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Review {
+    pub user: String,
+    pub state: ReviewState,
+}
+
+/// An open PR as listed, compared with the previous listing to find lifecycle events.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenPull {
+    pub number: i64,
+    pub title: String,
+    pub link: String,
+    pub draft: bool,
+    /// Requested reviewers' logins.
+    pub reviewers: Vec<String>,
+}
+
+/// The kinds of lifecycle event a watch can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    Opened,
+    Closed,
+    Merged,
+    Reopened,
+    ReadyForReview,
+    TitleChanged,
+    ReviewerAdded,
+    ReviewerRemoved,
+}
+
+impl Lifecycle {
+    pub const ALL: [Lifecycle; 8] = [
+        Lifecycle::Opened,
+        Lifecycle::Closed,
+        Lifecycle::Merged,
+        Lifecycle::Reopened,
+        Lifecycle::ReadyForReview,
+        Lifecycle::TitleChanged,
+        Lifecycle::ReviewerAdded,
+        Lifecycle::ReviewerRemoved,
+    ];
+}
+
+/// What is known about a tracked PR at one tick, compared with the previous tick to find changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PullState {
+    pub number: i64,
+    pub title: String,
+    pub link: String,
+    pub author: String,
+    /// Submitted reviews by id.
+    pub reviews: BTreeMap<u64, Review>,
+    /// Comments in the PR conversation by id, with their author.
+    pub comments: BTreeMap<u64, String>,
+    /// e.g. `clean`, `dirty` or `blocked`, `None` while the forge is still computing it.
+    pub mergeable: Option<String>,
+    /// Commits the head is behind the base branch, `None` unless stale PRs are checked.
+    pub behind: Option<u64>,
+    /// Combined state of the head commit's statuses and checks, `None` without any CI.
+    pub ci: Option<CiState>,
+    /// Written by one of the authored watch's users.
+    pub authored: bool,
+    /// Awaiting review from one of the account's reviewers.
+    pub review_requested: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CiState {
+    Pending,
+    Success,
+    Failure,
+}
+
+impl CiState {
+    /// Folds one status or check into the state of all of them, any failure wins over pending.
+    pub fn and(self, other: CiState) -> CiState {
+        match (self, other) {
+            (CiState::Failure, _) | (_, CiState::Failure) => CiState::Failure,
+            (CiState::Pending, _) | (_, CiState::Pending) => CiState::Pending,
+            _ => CiState::Success,
+        }
+    }
+}
+
+/// What polling one repo found.
+#[derive(Debug, Default)]
+pub struct Poll {
+    pub reviews: Vec<ReviewRequest>,
+    /// Tracked PRs, compared with the previous poll to notify changes.
+    pub pulls: Vec<PullState>,
+    /// Every open PR.
+    pub open: Vec<OpenPull>,
+}
+
+#[async_trait]
+pub trait Api: Send + Sync {
+    /// Polls `repo` once for review requests and the state of tracked PRs.
+    async fn execute(&self, token: &str, repo: &str) -> Result<Poll, Error>;
+    /// Every open PR, with why it is not notified or `None` when it is.
+    async fn preview(
+        &self,
+        token: &str,
+        repo: &str,
+    ) -> Result<Vec<(ReviewRequest, Option<String>)>, Error>;
+    /// Whether the closed PR `number` was merged.
+    async fn merged(&self, token: &str, repo: &str, number: i64) -> Result<bool, Error>;
+
+    /// Users who submitted a review on PR `number`, none where reviewers stay listed after
+    /// they review.
+    async fn reviewed(
+        &self,
+        _token: &str,
+        _repo: &str,
+        _number: i64,
+    ) -> Result<Vec<String>, Error> {
+        Ok(vec![])
+    }
+
+    /// Open issues and recent comments on `repo` that `filter` alerts on, none where the
+    /// forge has no issues.
+    async fn watch_issues(
+        &self,
+        _token: &str,
+        _repo: &str,
+        _filter: &IssueFilter,
+        _users: &[String],
+    ) -> Result<Vec<Event>, Error> {
+        Ok(vec![])
+    }
+
+    /// Every repo of the owner, or of the org when `org`.
+    async fn list_repos(&self, token: &str, org: bool) -> Result<Vec<Repo>, Error>;
+    async fn list_orgs(&self, token: &str) -> Result<Vec<Org>, Error>;
+
+    fn owner(&self) -> &str;
     fn headers(&self, token: &str) -> HeaderMap;
+    fn user(&self) -> String;
     fn repo(&self, repo: &str) -> String;
-    fn repos(&self) -> String;
-    fn org_repos(&self) -> String;
-    fn orgs(&self) -> String;
-    fn pull_requests(&self, repo: &str) -> String;
-    fn issues(&self, repo: &str) -> String;
-    fn reviews(&self, repo: &str, number: i64) -> String;
+    fn link(&self, repo: &str, number: i64) -> String;
     fn notify(&self, repo: &str, content: &str, pr: PullRequest);
+
+    /// The OAuth app used for device-flow sign in, `None` when the forge has no device flow.
+    fn client_id(&self) -> Option<String> {
+        None
+    }
+    fn device_code_url(&self) -> Option<String> {
+        None
+    }
+    fn access_token_url(&self) -> Option<String> {
+        None
+    }
+    fn scopes(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// What every plugin reads from its account.
+pub(crate) struct Watch {
+    pub account: String,
+    pub owner: String,
+    pub reviews: HashMap<String, ()>,
+    pub filter: PullFilter,
+    /// Whose PRs to track for the authored watch.
+    pub authors: Vec<String>,
+    /// Whether to read CI state for tracked PRs.
+    pub ci: bool,
+    /// Whether to read mergeability of PRs awaiting review too.
+    pub conflicts: bool,
+}
+
+impl Watch {
+    pub fn new(account: &Account, owner: String) -> Self {
+        Watch {
+            account: account.name().to_string(),
+            owner,
+            reviews: account.reviews(),
+            filter: account.pulls.clone(),
+            authors: account.authored_users().to_vec(),
+            ci: account.ci.enabled,
+            conflicts: account.conflicts.enabled,
+        }
+    }
+
+    fn review_request(&self, repo: &str, open: &OpenPull) -> ReviewRequest {
+        ReviewRequest {
+            account: self.account.clone(),
+            repo: repo.to_string(),
+            number: open.number,
+            title: open.title.clone(),
+            link: open.link.clone(),
+        }
+    }
+}
+
+/// How a plugin reads the open PRs of a repo, [`poll`] and [`preview`] do the rest.
+#[async_trait]
+pub(crate) trait Pulls: Api {
+    type Pull: Send + Sync;
+
+    fn watch(&self) -> &Watch;
+    async fn pulls(&self, token: &str, repo: &str) -> Result<Vec<Self::Pull>, Error>;
+    fn open(&self, repo: &str, pr: &Self::Pull) -> OpenPull;
+    fn author<'a>(&self, pr: &'a Self::Pull) -> &'a str;
+    /// Why `pr` is not notified, `None` when it is.
+    async fn reject(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &Self::Pull,
+    ) -> Result<Option<String>, Error>;
+    /// Reviews only matter to authors, CI and conflicts to both sides.
+    async fn pull_state(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &Self::Pull,
+        authored: bool,
+    ) -> Result<PullState, Error>;
+}
+
+/// Review requests among the open PRs of `repo`, and the state of those tracked.
+pub(crate) async fn poll<P: Pulls>(plugin: &P, token: &str, repo: &str) -> Result<Poll, Error> {
+    let watch = plugin.watch();
+    let mut poll = Poll::default();
+    for pr in &plugin.pulls(token, repo).await? {
+        let open = plugin.open(repo, pr);
+        let review_requested = plugin.reject(token, repo, pr).await?.is_none();
+        if review_requested {
+            poll.reviews.push(watch.review_request(repo, &open));
+        }
+        poll.open.push(open);
+        let author = plugin.author(pr);
+        let authored = watch
+            .authors
+            .iter()
+            .any(|user| user.eq_ignore_ascii_case(author));
+        if authored || ((watch.ci || watch.conflicts) && review_requested) {
+            let mut pull = plugin.pull_state(token, repo, pr, authored).await?;
+            pull.review_requested = review_requested;
+            poll.pulls.push(pull);
+        }
+    }
+    Ok(poll)
+}
+
+/// Every open PR of `repo`, with why it is not notified or `None` when it is.
+pub(crate) async fn preview<P: Pulls>(
+    plugin: &P,
+    token: &str,
+    repo: &str,
+) -> Result<Vec<(ReviewRequest, Option<String>)>, Error> {
+    let pulls = plugin.pulls(token, repo).await?;
+    let mut previews = Vec::with_capacity(pulls.len());
+    for pr in &pulls {
+        let review = plugin.watch().review_request(repo, &plugin.open(repo, pr));
+        previews.push((review, plugin.reject(token, repo, pr).await?));
+    }
+    Ok(previews)
+}
+
+/// Ids reviews by their content, for forges whose verdicts have none. A changed verdict reads
+/// as a new review.
+pub fn review_id(review: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    review.hash(&mut hasher);
+    hasher.finish()
 }
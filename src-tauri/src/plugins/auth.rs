@@ -0,0 +1,151 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::error::{AuthFailure, Error};
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+fn scopes(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Tells rate limits apart from auth failures and works out what the user has to fix.
+///
+/// Returns `None` for statuses that are not about credentials.
+pub fn classify(status: StatusCode, headers: &HeaderMap, message: &str) -> Option<Error> {
+    let rate_limited = header(headers, "x-ratelimit-remaining") == Some("0");
+    if status == StatusCode::TOO_MANY_REQUESTS || (status == StatusCode::FORBIDDEN && rate_limited)
+    {
+        return Some(Error::RateLimit(message.to_string()));
+    }
+    if !matches!(
+        status,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+    ) {
+        return None;
+    }
+
+    // e.g. `required; url=https://github.com/orgs/apache/sso?authorization_request=...`
+    if let Some(url) = header(headers, "x-github-sso").and_then(|sso| sso.split("url=").nth(1)) {
+        let org = url
+            .split("/orgs/")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next())
+            .unwrap_or_default();
+        return Some(Error::Auth(AuthFailure::Sso {
+            org: org.to_string(),
+            url: url.to_string(),
+        }));
+    }
+
+    if let (Some(required), Some(granted)) = (
+        header(headers, "x-accepted-oauth-scopes"),
+        header(headers, "x-oauth-scopes"),
+    ) {
+        let (required, granted) = (scopes(required), scopes(granted));
+        if !required.is_empty() && !required.iter().any(|scope| granted.contains(scope)) {
+            return Some(Error::Auth(AuthFailure::Scopes { required, granted }));
+        }
+    }
+
+    match status {
+        StatusCode::UNAUTHORIZED => Some(Error::Auth(AuthFailure::BadCredentials)),
+        StatusCode::FORBIDDEN => Some(Error::Auth(AuthFailure::Forbidden(message.to_string()))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    use crate::error::{AuthFailure, Error};
+    use crate::plugins::auth::classify;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_sso() {
+        let headers = headers(&[(
+            "x-github-sso",
+            "required; url=https://github.com/orgs/apache/sso?authorization_request=1",
+        )]);
+        assert_eq!(
+            classify(StatusCode::FORBIDDEN, &headers, ""),
+            Some(Error::Auth(AuthFailure::Sso {
+                org: "apache".to_string(),
+                url: "https://github.com/orgs/apache/sso?authorization_request=1".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        let headers = headers(&[
+            ("x-accepted-oauth-scopes", "repo"),
+            ("x-oauth-scopes", "read:org, gist"),
+        ]);
+        assert_eq!(
+            classify(StatusCode::NOT_FOUND, &headers, "Not Found"),
+            Some(Error::Auth(AuthFailure::Scopes {
+                required: vec!["repo".to_string()],
+                granted: vec!["read:org".to_string(), "gist".to_string()],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(
+            classify(
+                StatusCode::UNAUTHORIZED,
+                &HeaderMap::new(),
+                "Bad credentials"
+            ),
+            Some(Error::Auth(AuthFailure::BadCredentials))
+        );
+        assert!(matches!(
+            classify(
+                StatusCode::FORBIDDEN,
+                &headers(&[("x-ratelimit-remaining", "0")]),
+                "API rate limit exceeded"
+            ),
+            Some(Error::RateLimit(_))
+        ));
+        assert_eq!(
+            classify(StatusCode::NOT_FOUND, &HeaderMap::new(), "Not Found"),
+            None
+        );
+    }
+}
@@ -0,0 +1,492 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::conf::config::Account;
+use crate::conf::filter::Candidate;
+use crate::console::model::{Org, Repo};
+use crate::error::Error;
+use crate::notification::notify::notify;
+use crate::plugins::api::PullRequest as PR;
+use crate::plugins::api::ReviewRequest as RR;
+use crate::plugins::api::{
+    self, review_id, Api, CiState, OpenPull, Poll, PullState, Pulls, Review, ReviewState, Watch,
+};
+use crate::plugins::{get_client, MAX_PAGES};
+
+const API_VERSION: &str = "api-version=7.1";
+
+/// PRs fetched per request, the API pages with `$top` and `$skip`.
+const PAGE: usize = 100;
+
+/// Azure DevOps Repos, owners are `organization/project`.
+pub struct AzureDevOps {
+    watch: Watch,
+    /// The organization of `owner`, or of the account's owner when listing projects.
+    organization: String,
+    /// `https://dev.azure.com`, or the server's address up to the collections.
+    base: String,
+}
+
+impl AzureDevOps {
+    pub fn new(account: &Account, owner: String) -> Self {
+        let source = if owner.is_empty() {
+            &account.owners.name
+        } else {
+            &owner
+        };
+        let organization = source.split('/').next().unwrap_or_default().to_string();
+        let base = account
+            .base_url
+            .as_deref()
+            .unwrap_or("https://dev.azure.com")
+            .trim_end_matches('/')
+            .to_string();
+        AzureDevOps {
+            watch: Watch::new(account, owner),
+            organization,
+            base,
+        }
+    }
+
+    /// Folds the statuses posted to PR `number` into one state.
+    async fn ci_state(
+        &self,
+        token: &str,
+        repo: &str,
+        number: i64,
+    ) -> Result<Option<CiState>, Error> {
+        let url = self.pr_statuses(repo, number);
+        let statuses = list::<Status>(url, self.headers(token)).await?;
+        Ok(statuses
+            .iter()
+            .filter_map(|status| match status.state.as_str() {
+                "succeeded" => Some(CiState::Success),
+                "pending" => Some(CiState::Pending),
+                "failed" | "error" => Some(CiState::Failure),
+                // notApplicable and notSet say nothing about the build.
+                _ => None,
+            })
+            .reduce(CiState::and))
+    }
+
+    fn repos(&self) -> String {
+        format!(
+            "{}/{}/_apis/git/repositories?{API_VERSION}",
+            self.base, self.watch.owner
+        )
+    }
+
+    fn orgs(&self) -> String {
+        format!(
+            "{}/{}/_apis/projects?$top=1000&{API_VERSION}",
+            self.base, self.organization
+        )
+    }
+
+    fn pull_requests(&self, repo: &str) -> String {
+        format!(
+            "{}/{}/_apis/git/repositories/{repo}/pullrequests",
+            self.base, self.watch.owner
+        )
+    }
+
+    fn pull_request(&self, repo: &str, number: i64) -> String {
+        format!("{}/{number}", self.pull_requests(repo))
+    }
+
+    // Builds post their statuses to the PR rather than the commit.
+    fn pr_statuses(&self, repo: &str, number: i64) -> String {
+        format!("{}/statuses?{API_VERSION}", self.pull_request(repo, number))
+    }
+}
+
+/// The `value` of a list response.
+async fn list<T>(url: String, headers: HeaderMap) -> Result<Vec<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    Ok(get_client::<List<T>>(url, headers).await?.value)
+}
+
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Identity {
+    #[serde(default)]
+    unique_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Pull {
+    #[serde(rename = "pullRequestId")]
+    id: i64,
+    title: String,
+    #[serde(rename = "isDraft", default)]
+    draft: bool,
+    /// `active`, `completed` once merged or `abandoned` once closed.
+    #[serde(default)]
+    status: String,
+    created_by: Identity,
+    /// e.g. `refs/heads/main`.
+    target_ref_name: String,
+    merge_status: Option<String>,
+    #[serde(default)]
+    reviewers: Vec<Reviewer>,
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+impl Pull {
+    fn branch(&self) -> &str {
+        self.target_ref_name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&self.target_ref_name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reviewer {
+    id: String,
+    #[serde(default)]
+    unique_name: String,
+    #[serde(default)]
+    display_name: String,
+    /// 10 approved, 5 approved with suggestions, 0 no vote, -5 waiting for author, -10 rejected.
+    #[serde(default)]
+    vote: i64,
+    /// A group or team rather than a user.
+    #[serde(default)]
+    is_container: bool,
+}
+
+impl Reviewer {
+    /// Users by their unique name, groups such as `[Flexible]\Reviewers` by their display name.
+    fn name(&self) -> &str {
+        if self.is_container {
+            &self.display_name
+        } else {
+            &self.unique_name
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    state: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureRepo {
+    name: String,
+    #[serde(default)]
+    web_url: String,
+    #[serde(default)]
+    is_disabled: bool,
+    #[serde(default)]
+    is_fork: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    name: String,
+}
+
+#[async_trait]
+impl Pulls for AzureDevOps {
+    type Pull = Pull;
+
+    fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    async fn pulls(&self, token: &str, repo: &str) -> Result<Vec<Pull>, Error> {
+        let mut pulls = Vec::new();
+        for page in 0..MAX_PAGES {
+            let url = format!(
+                "{}?searchCriteria.status=active&$top={PAGE}&$skip={}&{API_VERSION}",
+                self.pull_requests(repo),
+                page * PAGE
+            );
+            let values = list::<Pull>(url, self.headers(token)).await?;
+            let last = values.len() < PAGE;
+            pulls.extend(values);
+            if last {
+                break;
+            }
+        }
+        Ok(pulls)
+    }
+
+    fn open(&self, repo: &str, pr: &Pull) -> OpenPull {
+        OpenPull {
+            number: pr.id,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.id),
+            draft: pr.draft,
+            reviewers: pr
+                .reviewers
+                .iter()
+                .map(|reviewer| reviewer.name().to_string())
+                .collect(),
+        }
+    }
+
+    fn author<'a>(&self, pr: &'a Pull) -> &'a str {
+        &pr.created_by.unique_name
+    }
+
+    // PR sizes would need the diff of every PR.
+    async fn reject(&self, _token: &str, _repo: &str, pr: &Pull) -> Result<Option<String>, Error> {
+        let candidate = Candidate {
+            draft: pr.draft,
+            labels: pr.labels.iter().map(|label| label.name.as_str()).collect(),
+            author: pr.created_by.unique_name.as_str(),
+            bot: false,
+            base: pr.branch(),
+        };
+        if let Some(reason) = self.watch.filter.reject(&candidate) {
+            return Ok(Some(reason));
+        }
+        // Reviewers stay listed after they vote, only those yet to vote are requested.
+        if !pr
+            .reviewers
+            .iter()
+            .any(|reviewer| reviewer.vote == 0 && self.watch.reviews.contains_key(reviewer.name()))
+        {
+            return Ok(Some("no watched reviewer requested".to_string()));
+        }
+        Ok(None)
+    }
+
+    async fn pull_state(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &Pull,
+        authored: bool,
+    ) -> Result<PullState, Error> {
+        let mut pull = PullState {
+            number: pr.id,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.id),
+            author: pr.created_by.unique_name.clone(),
+            authored,
+            ..Default::default()
+        };
+        if self.watch.ci {
+            pull.ci = self.ci_state(token, repo, pr.id).await?;
+        }
+        if authored || self.watch.conflicts {
+            pull.mergeable = match pr.merge_status.as_deref() {
+                Some("succeeded") => Some("clean".to_string()),
+                Some("conflicts") => Some("dirty".to_string()),
+                // Still being computed.
+                Some("queued" | "notSet") | None => None,
+                Some(_) => Some("blocked".to_string()),
+            };
+        }
+        if authored {
+            // Votes have no ids, a new vote of the same reviewer is a new review.
+            pull.reviews = pr
+                .reviewers
+                .iter()
+                .filter_map(|reviewer| {
+                    let state = match reviewer.vote {
+                        v if v > 0 => ReviewState::Approved,
+                        v if v < 0 => ReviewState::ChangesRequested,
+                        _ => return None,
+                    };
+                    let id = (reviewer.id.as_str(), reviewer.vote);
+                    let review = Review {
+                        user: reviewer.name().to_string(),
+                        state,
+                    };
+                    Some((review_id(id), review))
+                })
+                .collect();
+        }
+        Ok(pull)
+    }
+}
+
+#[async_trait]
+impl Api for AzureDevOps {
+    #[tracing::instrument(name = "repo", skip(self, token), fields(owner = self.watch.owner))]
+    async fn execute(&self, token: &str, repo: &str) -> Result<Poll, Error> {
+        api::poll(self, token, repo).await
+    }
+
+    async fn preview(&self, token: &str, repo: &str) -> Result<Vec<(RR, Option<String>)>, Error> {
+        api::preview(self, token, repo).await
+    }
+
+    async fn merged(&self, token: &str, repo: &str, number: i64) -> Result<bool, Error> {
+        let url = format!("{}?{API_VERSION}", self.pull_request(repo, number));
+        let pull = get_client::<Pull>(url, self.headers(token)).await?;
+        Ok(pull.status == "completed")
+    }
+
+    // A project is both owner and org.
+    async fn list_repos(&self, token: &str, _org: bool) -> Result<Vec<Repo>, Error> {
+        let repos = list::<AzureRepo>(self.repos(), self.headers(token)).await?;
+        Ok(repos
+            .into_iter()
+            .map(|repo| {
+                Repo::new(
+                    repo.name,
+                    repo.web_url,
+                    String::new(),
+                    repo.is_disabled,
+                    repo.is_fork,
+                )
+            })
+            .collect())
+    }
+
+    async fn list_orgs(&self, token: &str) -> Result<Vec<Org>, Error> {
+        let projects = list::<Project>(self.orgs(), self.headers(token)).await?;
+        Ok(projects
+            .into_iter()
+            .map(|project| Org::new(format!("{}/{}", self.organization, project.name)))
+            .collect())
+    }
+
+    fn owner(&self) -> &str {
+        &self.watch.owner
+    }
+
+    // A personal access token goes in the password of Basic auth, with no user name.
+    fn headers(&self, token: &str) -> HeaderMap {
+        let basic = format!("Basic {}", STANDARD.encode(format!(":{token}")));
+        let mut headers = HeaderMap::with_capacity(2);
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&basic).unwrap());
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    fn user(&self) -> String {
+        format!(
+            "{}/{}/_apis/connectionData?{API_VERSION}-preview",
+            self.base, self.organization
+        )
+    }
+
+    fn repo(&self, repo: &str) -> String {
+        format!(
+            "{}/{}/_apis/git/repositories/{repo}?{API_VERSION}",
+            self.base, self.watch.owner
+        )
+    }
+
+    fn link(&self, repo: &str, number: i64) -> String {
+        format!(
+            "{}/{}/_git/{repo}/pullrequest/{number}",
+            self.base, self.watch.owner
+        )
+    }
+
+    fn notify(&self, repo: &str, _: &str, pr: PR) {
+        notify(
+            self.watch.account.as_str(),
+            pr.title.as_str(),
+            self.link(repo, pr.number).as_str(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::config::Account;
+    use crate::plugins::api::{Api, CiState, ReviewState};
+    use crate::plugins::azure::AzureDevOps;
+    use crate::plugins::stub::{self, serve};
+
+    #[tokio::test]
+    async fn test_pulls() {
+        let statuses = include_str!("../../tests/fixtures/azure/statuses.json");
+        let base = serve(vec![
+            (200, include_str!("../../tests/fixtures/azure/pulls.json")),
+            (200, statuses),
+            (200, statuses),
+        ])
+        .await;
+        let mut account = stub::account(
+            "azure",
+            Some(base.clone()),
+            &["alice@contoso.com", r"[Flexible]\Reviewers"],
+            "bob@contoso.com",
+        );
+        account.ci.enabled = true;
+        let hub = AzureDevOps::new(&account, "contoso/Flexible".to_string());
+
+        let poll = hub.execute("token", "app").await.unwrap();
+        // Alice voted on #21 but its group reviewer has not.
+        assert_eq!(
+            poll.reviews.iter().map(|r| r.number).collect::<Vec<_>>(),
+            vec![21, 22]
+        );
+        assert_eq!(
+            poll.reviews[1].link,
+            format!("{base}/contoso/Flexible/_git/app/pullrequest/22")
+        );
+        assert_eq!(
+            poll.open[0].reviewers,
+            vec!["alice@contoso.com", r"[Flexible]\Reviewers"]
+        );
+
+        let authored = &poll.pulls[0];
+        assert!(authored.authored && authored.review_requested);
+        assert_eq!(authored.mergeable.as_deref(), Some("dirty"));
+        assert_eq!(authored.ci, Some(CiState::Failure));
+        let reviews: Vec<_> = authored.reviews.values().collect();
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(
+            (reviews[0].user.as_str(), reviews[0].state),
+            ("alice@contoso.com", ReviewState::Approved)
+        );
+        assert!(!poll.pulls[1].authored);
+    }
+
+    #[test]
+    fn test_headers() {
+        let hub = AzureDevOps::new(&Account::default(), "contoso/Flexible".to_string());
+        assert_eq!(hub.headers("pat")["authorization"], "Basic OnBhdA==");
+        assert_eq!(
+            hub.repo("app"),
+            "https://dev.azure.com/contoso/Flexible/_apis/git/repositories/app?api-version=7.1"
+        );
+    }
+}
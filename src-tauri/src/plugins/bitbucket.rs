@@ -0,0 +1,704 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::conf::config::Account;
+use crate::conf::filter::Candidate;
+use crate::console::model::{Org, Repo};
+use crate::error::Error;
+use crate::notification::notify::notify;
+use crate::plugins::api::PullRequest as PR;
+use crate::plugins::api::ReviewRequest as RR;
+use crate::plugins::api::{
+    self, review_id, Api, CiState, OpenPull, Poll, PullState, Pulls, Review, ReviewState, Watch,
+};
+use crate::plugins::{get_client, MAX_PAGES};
+
+/// Which REST API the account talks to, the two return different shapes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flavor {
+    /// bitbucket.org, API 2.0. Owners are workspaces.
+    Cloud,
+    /// Bitbucket Data Center or Server, REST 1.0. Owners are project keys, `~user` for
+    /// personal repos.
+    Server,
+}
+
+pub struct Bitbucket {
+    watch: Watch,
+    flavor: Flavor,
+    api: String,
+    web: String,
+}
+
+impl Bitbucket {
+    pub fn new(account: &Account, owner: String) -> Self {
+        // Without a base url the account is on bitbucket.org, otherwise on Data Center.
+        let (flavor, api, web) = match account.base_url.as_deref() {
+            Some(base) => {
+                let base = base.trim_end_matches('/');
+                (
+                    Flavor::Server,
+                    format!("{base}/rest/api/1.0"),
+                    base.to_string(),
+                )
+            }
+            None => (
+                Flavor::Cloud,
+                "https://api.bitbucket.org/2.0".to_string(),
+                "https://bitbucket.org".to_string(),
+            ),
+        };
+        Bitbucket {
+            watch: Watch::new(account, owner),
+            flavor,
+            api,
+            web,
+        }
+    }
+
+    async fn pull(&self, token: &str, repo: &str, number: i64) -> Result<Pull, Error> {
+        let url = self.pull_request(repo, number);
+        Ok(match self.flavor {
+            Flavor::Cloud => get_client::<CloudPull>(url, self.headers(token))
+                .await?
+                .into(),
+            Flavor::Server => get_client::<ServerPull>(url, self.headers(token))
+                .await?
+                .into(),
+        })
+    }
+
+    /// Folds the build statuses of `sha` into one state.
+    async fn ci_state(&self, token: &str, repo: &str, sha: &str) -> Result<Option<CiState>, Error> {
+        let statuses =
+            pages::<BuildStatus>(self.commit_status(repo, sha), self.headers(token)).await?;
+        Ok(statuses
+            .iter()
+            .map(|status| match status.state.as_str() {
+                "SUCCESSFUL" => CiState::Success,
+                "INPROGRESS" => CiState::Pending,
+                _ => CiState::Failure,
+            })
+            .reduce(CiState::and))
+    }
+
+    fn repos(&self) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/repositories/{}?pagelen=100", self.api, self.watch.owner),
+            Flavor::Server => format!("{}/projects/{}/repos?limit=100", self.api, self.watch.owner),
+        }
+    }
+
+    fn orgs(&self) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/workspaces?pagelen=100", self.api),
+            Flavor::Server => format!("{}/projects?limit=100", self.api),
+        }
+    }
+
+    fn pull_requests(&self, repo: &str) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/pullrequests", self.repo(repo)),
+            Flavor::Server => format!("{}/pull-requests", self.repo(repo)),
+        }
+    }
+
+    fn pull_request(&self, repo: &str, number: i64) -> String {
+        format!("{}/{number}", self.pull_requests(repo))
+    }
+
+    fn commit_status(&self, repo: &str, sha: &str) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/commit/{sha}/statuses?pagelen=100", self.repo(repo)),
+            Flavor::Server => format!("{}/rest/build-status/1.0/commits/{sha}?limit=100", self.web),
+        }
+    }
+}
+
+/// Fetches `url` and the pages after it, following Cloud's `next` link or Server's
+/// `nextPageStart`. `url` must already have a query.
+async fn pages<T>(url: String, headers: HeaderMap) -> Result<Vec<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut items = Vec::new();
+    let mut next = Some(url.clone());
+    for _ in 0..MAX_PAGES {
+        let Some(page_url) = next.take() else {
+            break;
+        };
+        let page = get_client::<Page<T>>(page_url, headers.clone()).await?;
+        items.extend(page.values);
+        next = match (page.next, page.next_page_start) {
+            (Some(link), _) => Some(link),
+            (None, Some(start)) if !page.is_last_page => Some(format!("{url}&start={start}")),
+            _ => None,
+        };
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    values: Vec<T>,
+    next: Option<String>,
+    #[serde(rename = "nextPageStart")]
+    next_page_start: Option<u64>,
+    #[serde(rename = "isLastPage", default)]
+    is_last_page: bool,
+}
+
+/// A PR in the shape both APIs are read into.
+#[derive(Debug)]
+pub(crate) struct Pull {
+    number: i64,
+    title: String,
+    draft: bool,
+    author: String,
+    state: String,
+    base: String,
+    /// Sha of the head commit, empty when not listed.
+    head: String,
+    participants: Vec<Participant>,
+}
+
+#[derive(Debug)]
+struct Participant {
+    user: String,
+    reviewer: bool,
+    /// `None` until the participant approves or asks for changes.
+    state: Option<ReviewState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudUser {
+    nickname: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudPull {
+    id: i64,
+    title: String,
+    #[serde(default)]
+    draft: bool,
+    state: String,
+    author: CloudUser,
+    source: CloudEnd,
+    destination: CloudEnd,
+    #[serde(default)]
+    participants: Vec<CloudParticipant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudEnd {
+    branch: CloudBranch,
+    commit: Option<CloudCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudBranch {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudCommit {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudParticipant {
+    user: CloudUser,
+    role: String,
+    approved: bool,
+    state: Option<String>,
+}
+
+impl From<CloudPull> for Pull {
+    fn from(pull: CloudPull) -> Self {
+        Pull {
+            number: pull.id,
+            title: pull.title,
+            draft: pull.draft,
+            author: pull.author.nickname,
+            state: pull.state,
+            base: pull.destination.branch.name,
+            head: pull
+                .source
+                .commit
+                .map(|commit| commit.hash)
+                .unwrap_or_default(),
+            participants: pull
+                .participants
+                .into_iter()
+                .map(|participant| Participant {
+                    user: participant.user.nickname,
+                    reviewer: participant.role == "REVIEWER",
+                    state: match participant.state.as_deref() {
+                        Some("changes_requested") => Some(ReviewState::ChangesRequested),
+                        _ if participant.approved => Some(ReviewState::Approved),
+                        _ => None,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerUser {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerPull {
+    id: i64,
+    title: String,
+    #[serde(default)]
+    draft: bool,
+    state: String,
+    author: ServerParticipant,
+    from_ref: ServerRef,
+    to_ref: ServerRef,
+    #[serde(default)]
+    reviewers: Vec<ServerParticipant>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerRef {
+    display_id: String,
+    #[serde(default)]
+    latest_commit: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerParticipant {
+    user: ServerUser,
+    role: String,
+    status: String,
+}
+
+impl From<ServerPull> for Pull {
+    fn from(pull: ServerPull) -> Self {
+        Pull {
+            number: pull.id,
+            title: pull.title,
+            draft: pull.draft,
+            author: pull.author.user.name,
+            state: pull.state,
+            base: pull.to_ref.display_id,
+            head: pull.from_ref.latest_commit,
+            participants: pull
+                .reviewers
+                .into_iter()
+                .map(|participant| Participant {
+                    user: participant.user.name,
+                    reviewer: participant.role == "REVIEWER",
+                    state: match participant.status.as_str() {
+                        "APPROVED" => Some(ReviewState::Approved),
+                        "NEEDS_WORK" => Some(ReviewState::ChangesRequested),
+                        _ => None,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Merge {
+    can_merge: bool,
+    conflicted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildStatus {
+    state: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Link {
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudRepo {
+    slug: String,
+    links: CloudLinks,
+    #[serde(default)]
+    updated_on: String,
+    parent: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudLinks {
+    html: Link,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerRepo {
+    slug: String,
+    links: ServerLinks,
+    #[serde(default)]
+    archived: bool,
+    origin: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerLinks {
+    #[serde(rename = "self", default)]
+    web: Vec<Link>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudWorkspace {
+    slug: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerProject {
+    key: String,
+}
+
+#[async_trait]
+impl Pulls for Bitbucket {
+    type Pull = Pull;
+
+    fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    async fn pulls(&self, token: &str, repo: &str) -> Result<Vec<Pull>, Error> {
+        let headers = self.headers(token);
+        match self.flavor {
+            Flavor::Cloud => {
+                // The listing leaves participants out unless asked for.
+                let url = format!(
+                    "{}?state=OPEN&pagelen=50&fields=%2Bvalues.participants",
+                    self.pull_requests(repo)
+                );
+                let pulls = pages::<CloudPull>(url, headers).await?;
+                Ok(pulls.into_iter().map(Pull::from).collect())
+            }
+            Flavor::Server => {
+                let url = format!("{}?state=OPEN&limit=100", self.pull_requests(repo));
+                let pulls = pages::<ServerPull>(url, headers).await?;
+                Ok(pulls.into_iter().map(Pull::from).collect())
+            }
+        }
+    }
+
+    fn open(&self, repo: &str, pr: &Pull) -> OpenPull {
+        OpenPull {
+            number: pr.number,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.number),
+            draft: pr.draft,
+            reviewers: pr
+                .participants
+                .iter()
+                .filter(|participant| participant.reviewer)
+                .map(|participant| participant.user.clone())
+                .collect(),
+        }
+    }
+
+    fn author<'a>(&self, pr: &'a Pull) -> &'a str {
+        &pr.author
+    }
+
+    // Bitbucket has no labels or bot accounts, and sizes would need the diff of every PR.
+    async fn reject(&self, _token: &str, _repo: &str, pr: &Pull) -> Result<Option<String>, Error> {
+        let candidate = Candidate {
+            draft: pr.draft,
+            labels: vec![],
+            author: pr.author.as_str(),
+            bot: false,
+            base: pr.base.as_str(),
+        };
+        if let Some(reason) = self.watch.filter.reject(&candidate) {
+            return Ok(Some(reason));
+        }
+        // Reviewers stay listed after they answer, only those yet to answer are requested.
+        if !pr.participants.iter().any(|participant| {
+            participant.reviewer
+                && participant.state.is_none()
+                && self.watch.reviews.contains_key(participant.user.as_str())
+        }) {
+            return Ok(Some("no watched reviewer requested".to_string()));
+        }
+        Ok(None)
+    }
+
+    async fn pull_state(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &Pull,
+        authored: bool,
+    ) -> Result<PullState, Error> {
+        let mut pull = PullState {
+            number: pr.number,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.number),
+            author: pr.author.clone(),
+            authored,
+            ..Default::default()
+        };
+        if self.watch.ci && !pr.head.is_empty() {
+            pull.ci = self.ci_state(token, repo, &pr.head).await?;
+        }
+        // Cloud does not tell whether a PR merges cleanly without trying it.
+        if (authored || self.watch.conflicts) && self.flavor == Flavor::Server {
+            let url = format!("{}/merge", self.pull_request(repo, pr.number));
+            let merge = get_client::<Merge>(url, self.headers(token)).await?;
+            pull.mergeable = Some(
+                match (merge.conflicted, merge.can_merge) {
+                    (true, _) => "dirty",
+                    (false, true) => "clean",
+                    (false, false) => "blocked",
+                }
+                .to_string(),
+            );
+        }
+        if authored {
+            // Participants have no review ids, a new verdict of the same user is a new review.
+            pull.reviews = pr
+                .participants
+                .iter()
+                .filter_map(|participant| {
+                    let state = participant.state?;
+                    let review = Review {
+                        user: participant.user.clone(),
+                        state,
+                    };
+                    Some((review_id((&participant.user, state as u8)), review))
+                })
+                .collect();
+        }
+        Ok(pull)
+    }
+}
+
+#[async_trait]
+impl Api for Bitbucket {
+    #[tracing::instrument(name = "repo", skip(self, token), fields(owner = self.watch.owner))]
+    async fn execute(&self, token: &str, repo: &str) -> Result<Poll, Error> {
+        api::poll(self, token, repo).await
+    }
+
+    async fn preview(&self, token: &str, repo: &str) -> Result<Vec<(RR, Option<String>)>, Error> {
+        api::preview(self, token, repo).await
+    }
+
+    async fn merged(&self, token: &str, repo: &str, number: i64) -> Result<bool, Error> {
+        Ok(self.pull(token, repo, number).await?.state == "MERGED")
+    }
+
+    // Workspaces and projects are both owners and orgs.
+    async fn list_repos(&self, token: &str, _org: bool) -> Result<Vec<Repo>, Error> {
+        let url = self.repos();
+        let headers = self.headers(token);
+        Ok(match self.flavor {
+            Flavor::Cloud => pages::<CloudRepo>(url, headers)
+                .await?
+                .into_iter()
+                .map(|repo| {
+                    let fork = repo.parent.is_some();
+                    Repo::new(
+                        repo.slug,
+                        repo.links.html.href,
+                        repo.updated_on,
+                        false,
+                        fork,
+                    )
+                })
+                .collect(),
+            Flavor::Server => pages::<ServerRepo>(url, headers)
+                .await?
+                .into_iter()
+                .map(|repo| {
+                    let link = repo.links.web.into_iter().next().map(|link| link.href);
+                    let fork = repo.origin.is_some();
+                    Repo::new(
+                        repo.slug,
+                        link.unwrap_or_default(),
+                        String::new(),
+                        repo.archived,
+                        fork,
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    async fn list_orgs(&self, token: &str) -> Result<Vec<Org>, Error> {
+        let headers = self.headers(token);
+        Ok(match self.flavor {
+            Flavor::Cloud => pages::<CloudWorkspace>(self.orgs(), headers)
+                .await?
+                .into_iter()
+                .map(|workspace| Org::new(workspace.slug))
+                .collect(),
+            Flavor::Server => pages::<ServerProject>(self.orgs(), headers)
+                .await?
+                .into_iter()
+                .map(|project| Org::new(project.key))
+                .collect(),
+        })
+    }
+
+    fn owner(&self) -> &str {
+        &self.watch.owner
+    }
+
+    fn headers(&self, token: &str) -> HeaderMap {
+        let bearer_token = format!("Bearer {}", token);
+        let mut headers = HeaderMap::with_capacity(2);
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token).unwrap());
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    fn user(&self) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/user", self.api),
+            // Server has no endpoint for the current user, this one needs a signed in one.
+            Flavor::Server => format!("{}/dashboard/pull-requests?limit=1", self.api),
+        }
+    }
+
+    fn repo(&self, repo: &str) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!("{}/repositories/{}/{repo}", self.api, self.watch.owner),
+            Flavor::Server => format!("{}/projects/{}/repos/{repo}", self.api, self.watch.owner),
+        }
+    }
+
+    fn link(&self, repo: &str, number: i64) -> String {
+        match self.flavor {
+            Flavor::Cloud => format!(
+                "{}/{}/{repo}/pull-requests/{number}",
+                self.web, self.watch.owner
+            ),
+            Flavor::Server => format!(
+                "{}/projects/{}/repos/{repo}/pull-requests/{number}",
+                self.web, self.watch.owner
+            ),
+        }
+    }
+
+    fn notify(&self, repo: &str, _: &str, pr: PR) {
+        notify(
+            self.watch.account.as_str(),
+            pr.title.as_str(),
+            self.link(repo, pr.number).as_str(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::config::Account;
+    use crate::plugins::api::{Api, ReviewState};
+    use crate::plugins::bitbucket::Bitbucket;
+    use crate::plugins::stub::{self, serve};
+
+    fn account(base_url: Option<String>) -> Account {
+        let mut account = stub::account("bitbucket", base_url, &["alice"], "bob");
+        account.conflicts.enabled = true;
+        account
+    }
+
+    #[tokio::test]
+    async fn test_cloud_pulls() {
+        let base = serve(vec![(
+            200,
+            include_str!("../../tests/fixtures/bitbucket/cloud_pulls.json"),
+        )])
+        .await;
+        let mut hub = Bitbucket::new(&account(None), "flexible".to_string());
+        hub.api = base;
+
+        let poll = hub.execute("token", "app").await.unwrap();
+        assert_eq!(
+            poll.reviews.iter().map(|r| r.number).collect::<Vec<_>>(),
+            vec![7]
+        );
+        assert_eq!(
+            poll.reviews[0].link,
+            "https://bitbucket.org/flexible/app/pull-requests/7"
+        );
+        assert_eq!(poll.open.len(), 2);
+        // #7 awaits review and is tracked for conflicts, which Cloud leaves unknown.
+        assert_eq!(poll.pulls.len(), 2);
+        let pull = &poll.pulls[1];
+        assert_eq!((pull.number, pull.authored), (8, true));
+        assert_eq!(pull.mergeable, None);
+        let reviews: Vec<_> = pull.reviews.values().collect();
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(
+            (reviews[0].user.as_str(), reviews[0].state),
+            ("alice", ReviewState::Approved)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_pulls() {
+        let merge = include_str!("../../tests/fixtures/bitbucket/server_merge.json");
+        let base = serve(vec![
+            (
+                200,
+                include_str!("../../tests/fixtures/bitbucket/server_pulls_1.json"),
+            ),
+            (
+                200,
+                include_str!("../../tests/fixtures/bitbucket/server_pulls_2.json"),
+            ),
+            (200, merge),
+            (200, merge),
+        ])
+        .await;
+        let hub = Bitbucket::new(&account(Some(base.clone())), "FLEX".to_string());
+
+        let poll = hub.execute("token", "app").await.unwrap();
+        assert_eq!(
+            poll.reviews.iter().map(|r| r.number).collect::<Vec<_>>(),
+            vec![13]
+        );
+        assert_eq!(
+            poll.reviews[0].link,
+            format!("{base}/projects/FLEX/repos/app/pull-requests/13")
+        );
+        assert_eq!(poll.open.len(), 2);
+        let authored = &poll.pulls[0];
+        assert_eq!((authored.number, authored.authored), (12, true));
+        assert_eq!(authored.mergeable.as_deref(), Some("dirty"));
+        assert_eq!(
+            authored.reviews.values().next().map(|review| review.state),
+            Some(ReviewState::ChangesRequested)
+        );
+        assert!(poll.pulls[1].review_requested && !poll.pulls[1].authored);
+    }
+}
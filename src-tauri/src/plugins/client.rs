@@ -0,0 +1,102 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy};
+
+use crate::conf::config::Http;
+use crate::error::Error;
+
+pub const USER_AGENT: &str = concat!("flexible/", env!("CARGO_PKG_VERSION"));
+
+static CLIENT: LazyLock<RwLock<Client>> =
+    LazyLock::new(|| RwLock::new(build(&Http::default()).expect("default http client")));
+
+tokio::task_local! {
+    static SCOPED: Client;
+}
+
+/// The client every plugin request goes through, the [`scoped`] one when set.
+pub fn shared() -> Client {
+    SCOPED
+        .try_with(Client::clone)
+        .unwrap_or_else(|_| CLIENT.read().unwrap().clone())
+}
+
+/// Rebuilds the shared client from the `http` config section, only the scheduler does this.
+pub fn configure(http: &Http) -> Result<(), Error> {
+    *CLIENT.write().unwrap() = build(http)?;
+    Ok(())
+}
+
+/// Runs `fut` with `client` in place of the shared one, for one-off calls that must not replace it.
+pub async fn scoped<F: Future>(client: Client, fut: F) -> F::Output {
+    SCOPED.scope(client, fut).await
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|err| Error::Config(format!("{}: {err}", path.display())))
+}
+
+fn invalid(what: &str) -> impl Fn(reqwest::Error) -> Error + '_ {
+    move |err| Error::Config(format!("http.{what}: {err}"))
+}
+
+pub fn build(http: &Http) -> Result<Client, Error> {
+    let mut builder = Client::builder()
+        .user_agent(http.user_agent.as_deref().unwrap_or(USER_AGENT))
+        .connect_timeout(Duration::from_secs(http.connect_timeout))
+        .read_timeout(Duration::from_secs(http.read_timeout));
+
+    if let Some(proxy) = &http.proxy {
+        let no_proxy = NoProxy::from_string(http.no_proxy.join(",").as_str());
+        builder = builder.proxy(
+            Proxy::all(proxy.as_str())
+                .map_err(invalid("proxy"))?
+                .no_proxy(no_proxy),
+        );
+    }
+
+    for path in &http.root_certificates {
+        for cert in
+            Certificate::from_pem_bundle(&read(path)?).map_err(invalid("root_certificates"))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    match (&http.client_certificate, &http.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                .map_err(invalid("client_certificate"))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(Error::Config(
+                "http.client_certificate and http.client_key must be set together".to_string(),
+            ))
+        }
+    }
+
+    builder.build().map_err(invalid("client"))
+}
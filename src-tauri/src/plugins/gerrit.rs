@@ -0,0 +1,511 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeSet, HashMap};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::conf::config::Account;
+use crate::conf::filter::Candidate;
+use crate::console::model::{Org, Repo};
+use crate::error::Error;
+use crate::notification::notify::notify;
+use crate::plugins::api::PullRequest as PR;
+use crate::plugins::api::ReviewRequest as RR;
+use crate::plugins::api::{
+    self, review_id, Api, CiState, OpenPull, Poll, PullState, Pulls, Review, ReviewState, Watch,
+};
+use crate::plugins::{get_client, MAX_PAGES};
+
+/// What each listed change carries, votes and reviewers need the detailed forms.
+const OPTIONS: &str =
+    "o=DETAILED_LABELS&o=DETAILED_ACCOUNTS&o=CURRENT_REVISION&o=SUBMITTABLE&o=REVIEWED";
+
+/// Changes fetched per request.
+const PAGE: usize = 100;
+
+/// The reviewer name that stands for the token's own account.
+const SELF: &str = "self";
+
+/// Gerrit Code Review. Owners are project prefixes, e.g. `platform` for `platform/build`, and
+/// changes are PRs numbered by their change number.
+pub struct Gerrit {
+    watch: Watch,
+    base: String,
+}
+
+impl Gerrit {
+    pub fn new(account: &Account, owner: String) -> Result<Self, Error> {
+        let Some(base) = account.base_url.as_deref() else {
+            return Err(Error::Plugin(
+                "gerrit needs the base_url of the server".to_string(),
+            ));
+        };
+        Ok(Gerrit {
+            watch: Watch::new(account, owner),
+            base: base.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn project(&self, repo: &str) -> String {
+        if self.watch.owner.is_empty() {
+            repo.to_string()
+        } else {
+            format!("{}/{repo}", self.watch.owner)
+        }
+    }
+
+    /// Identifies change `number` of `repo` in urls.
+    fn change_id(&self, repo: &str, number: i64) -> String {
+        format!("{}~{number}", self.project(repo).replace('/', "%2F"))
+    }
+
+    /// Every change `url` finds, following `_more_changes`.
+    async fn changes(&self, token: &str, url: String) -> Result<Vec<Change>, Error> {
+        let mut changes = Vec::new();
+        for page in 0..MAX_PAGES {
+            let url = format!("{url}&{OPTIONS}&n={PAGE}&S={}", page * PAGE);
+            let found = get_client::<Vec<Change>>(url, self.headers(token)).await?;
+            let more = found.last().is_some_and(|change| change.more_changes);
+            changes.extend(found);
+            if !more {
+                break;
+            }
+        }
+        Ok(changes)
+    }
+
+    fn repos(&self) -> String {
+        if self.watch.owner.is_empty() {
+            format!("{}/a/projects/", self.base)
+        } else {
+            format!("{}/a/projects/?p={}/", self.base, self.watch.owner)
+        }
+    }
+
+    fn orgs(&self) -> String {
+        format!("{}/a/projects/", self.base)
+    }
+
+    fn pull_requests(&self, repo: &str) -> String {
+        format!(
+            "{}/a/changes/?q=status:open+project:{}",
+            self.base,
+            self.project(repo)
+        )
+    }
+
+    fn pull_request(&self, repo: &str, number: i64) -> String {
+        format!("{}/a/changes/{}", self.base, self.change_id(repo, number))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Change {
+    #[serde(rename = "_number")]
+    number: i64,
+    branch: String,
+    subject: String,
+    /// `NEW`, `MERGED` or `ABANDONED`.
+    status: String,
+    #[serde(default)]
+    work_in_progress: bool,
+    #[serde(default)]
+    hashtags: Vec<String>,
+    owner: Person,
+    #[serde(default)]
+    labels: HashMap<String, Label>,
+    /// Keyed by `REVIEWER`, `CC` and `REMOVED`.
+    #[serde(default)]
+    reviewers: HashMap<String, Vec<Person>>,
+    current_revision: Option<String>,
+    #[serde(default)]
+    revisions: HashMap<String, Revision>,
+    /// Only set where the server computes mergeability.
+    mergeable: Option<bool>,
+    submittable: Option<bool>,
+    /// Whether the token's account reviewed the current patch set.
+    #[serde(default)]
+    reviewed: bool,
+    #[serde(rename = "_more_changes", default)]
+    more_changes: bool,
+    /// Whether the token's account is a reviewer, found by a second query.
+    #[serde(skip)]
+    own: bool,
+}
+
+impl Change {
+    fn reviewers(&self) -> &[Person] {
+        self.reviewers.get("REVIEWER").map_or(&[], Vec::as_slice)
+    }
+
+    fn code_review(&self) -> &[Vote] {
+        self.labels
+            .get("Code-Review")
+            .map_or(&[], |label| label.all.as_slice())
+    }
+
+    fn patch_set(&self) -> i64 {
+        self.current_revision
+            .as_ref()
+            .and_then(|revision| self.revisions.get(revision))
+            .map_or(0, |revision| revision.number)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Person {
+    #[serde(rename = "_account_id")]
+    id: u64,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    email: String,
+}
+
+impl Person {
+    /// The username, or the email of accounts without one.
+    fn name(&self) -> &str {
+        if self.username.is_empty() {
+            &self.email
+        } else {
+            &self.username
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    #[serde(default)]
+    all: Vec<Vote>,
+    approved: Option<IgnoredAny>,
+    rejected: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vote {
+    #[serde(flatten)]
+    who: Person,
+    #[serde(default)]
+    value: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Revision {
+    #[serde(rename = "_number")]
+    number: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    /// `ACTIVE`, `READ_ONLY` or `HIDDEN`.
+    #[serde(default)]
+    state: String,
+}
+
+#[async_trait]
+impl Pulls for Gerrit {
+    type Pull = Change;
+
+    fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    // Marks the changes the token's account reviews when `self` is a watched reviewer.
+    async fn pulls(&self, token: &str, repo: &str) -> Result<Vec<Change>, Error> {
+        let mut changes = self.changes(token, self.pull_requests(repo)).await?;
+        if self.watch.reviews.contains_key(SELF) {
+            let url = format!(
+                "{}/a/changes/?q=reviewer:self+status:open+project:{}",
+                self.base,
+                self.project(repo)
+            );
+            let own: BTreeSet<i64> = self
+                .changes(token, url)
+                .await?
+                .iter()
+                .map(|change| change.number)
+                .collect();
+            for change in &mut changes {
+                change.own = own.contains(&change.number);
+            }
+        }
+        Ok(changes)
+    }
+
+    fn open(&self, repo: &str, change: &Change) -> OpenPull {
+        OpenPull {
+            number: change.number,
+            title: change.subject.clone(),
+            link: self.link(repo, change.number),
+            draft: change.work_in_progress,
+            reviewers: change
+                .reviewers()
+                .iter()
+                .map(|reviewer| reviewer.name().to_string())
+                .collect(),
+        }
+    }
+
+    fn author<'a>(&self, change: &'a Change) -> &'a str {
+        change.owner.name()
+    }
+
+    // Gerrit has no bot accounts, and sizes would need the diff of every change.
+    async fn reject(
+        &self,
+        _token: &str,
+        _repo: &str,
+        change: &Change,
+    ) -> Result<Option<String>, Error> {
+        let candidate = Candidate {
+            draft: change.work_in_progress,
+            labels: change.hashtags.iter().map(String::as_str).collect(),
+            author: change.owner.name(),
+            bot: false,
+            base: change.branch.as_str(),
+        };
+        if let Some(reason) = self.watch.filter.reject(&candidate) {
+            return Ok(Some(reason));
+        }
+        // Reviewers stay listed after they vote, only those yet to vote are requested.
+        let votes = change.code_review();
+        let waiting = change.reviewers().iter().any(|reviewer| {
+            self.watch.reviews.contains_key(reviewer.name())
+                && !votes
+                    .iter()
+                    .any(|vote| vote.who.id == reviewer.id && vote.value != 0)
+        });
+        let own = change.own && !change.reviewed;
+        if !waiting && !own {
+            return Ok(Some("no watched reviewer requested".to_string()));
+        }
+        Ok(None)
+    }
+
+    async fn pull_state(
+        &self,
+        _token: &str,
+        repo: &str,
+        change: &Change,
+        authored: bool,
+    ) -> Result<PullState, Error> {
+        let mut pull = PullState {
+            number: change.number,
+            title: change.subject.clone(),
+            link: self.link(repo, change.number),
+            author: change.owner.name().to_string(),
+            authored,
+            ..Default::default()
+        };
+        // Builds report through the Verified label.
+        if self.watch.ci {
+            pull.ci = change.labels.get("Verified").map(|label| {
+                if label.rejected.is_some() {
+                    CiState::Failure
+                } else if label.approved.is_some() {
+                    CiState::Success
+                } else {
+                    CiState::Pending
+                }
+            });
+        }
+        if authored || self.watch.conflicts {
+            pull.mergeable = match (change.mergeable, change.submittable) {
+                (Some(false), _) => Some("dirty".to_string()),
+                (_, Some(true)) => Some("clean".to_string()),
+                (_, Some(false)) => Some("blocked".to_string()),
+                _ => None,
+            };
+        }
+        if authored {
+            // Votes have no ids and start over on each patch set, which a new vote is.
+            let patch_set = change.patch_set();
+            pull.reviews = change
+                .code_review()
+                .iter()
+                .filter_map(|vote| {
+                    let state = match vote.value {
+                        v if v > 0 => ReviewState::Approved,
+                        v if v < 0 => ReviewState::ChangesRequested,
+                        _ => return None,
+                    };
+                    let review = Review {
+                        user: vote.who.name().to_string(),
+                        state,
+                    };
+                    Some((review_id((vote.who.id, vote.value, patch_set)), review))
+                })
+                .collect();
+        }
+        Ok(pull)
+    }
+}
+
+#[async_trait]
+impl Api for Gerrit {
+    #[tracing::instrument(name = "repo", skip(self, token), fields(owner = self.watch.owner))]
+    async fn execute(&self, token: &str, repo: &str) -> Result<Poll, Error> {
+        api::poll(self, token, repo).await
+    }
+
+    async fn preview(&self, token: &str, repo: &str) -> Result<Vec<(RR, Option<String>)>, Error> {
+        api::preview(self, token, repo).await
+    }
+
+    async fn merged(&self, token: &str, repo: &str, number: i64) -> Result<bool, Error> {
+        let change =
+            get_client::<Change>(self.pull_request(repo, number), self.headers(token)).await?;
+        Ok(change.status == "MERGED")
+    }
+
+    // Projects come as a map by name.
+    async fn list_repos(&self, token: &str, _org: bool) -> Result<Vec<Repo>, Error> {
+        let projects =
+            get_client::<HashMap<String, Project>>(self.repos(), self.headers(token)).await?;
+        let prefix = self.project("");
+        Ok(projects
+            .into_iter()
+            .filter_map(|(name, project)| {
+                let repo = name.strip_prefix(&prefix)?.to_string();
+                let link = format!("{}/admin/repos/{name}", self.base);
+                let archived = project.state == "READ_ONLY";
+                Some(Repo::new(repo, link, String::new(), archived, false))
+            })
+            .collect())
+    }
+
+    // Orgs are the top-level prefixes of the projects.
+    async fn list_orgs(&self, token: &str) -> Result<Vec<Org>, Error> {
+        let projects =
+            get_client::<HashMap<String, Project>>(self.orgs(), self.headers(token)).await?;
+        let prefixes: BTreeSet<_> = projects
+            .keys()
+            .filter_map(|name| Some(name.split_once('/')?.0.to_string()))
+            .collect();
+        Ok(prefixes.into_iter().map(Org::new).collect())
+    }
+
+    fn owner(&self) -> &str {
+        &self.watch.owner
+    }
+
+    // The token is `username:http-password`, generated in the user's settings on the server.
+    fn headers(&self, token: &str) -> HeaderMap {
+        let basic = format!("Basic {}", STANDARD.encode(token));
+        let mut headers = HeaderMap::with_capacity(2);
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&basic).unwrap());
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    fn user(&self) -> String {
+        format!("{}/a/accounts/self", self.base)
+    }
+
+    fn repo(&self, repo: &str) -> String {
+        format!(
+            "{}/a/projects/{}",
+            self.base,
+            self.project(repo).replace('/', "%2F")
+        )
+    }
+
+    fn link(&self, repo: &str, number: i64) -> String {
+        format!("{}/c/{}/+/{number}", self.base, self.project(repo))
+    }
+
+    fn notify(&self, repo: &str, _: &str, pr: PR) {
+        notify(
+            self.watch.account.as_str(),
+            pr.title.as_str(),
+            self.link(repo, pr.number).as_str(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::config::Account;
+    use crate::plugins::api::{Api, CiState, ReviewState};
+    use crate::plugins::gerrit::Gerrit;
+    use crate::plugins::get_api;
+    use crate::plugins::stub::{self, serve};
+
+    fn account(base_url: Option<String>, reviews: &[&str]) -> Account {
+        let mut account = stub::account("gerrit", base_url, reviews, "bob");
+        account.ci.enabled = true;
+        account
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let base = serve(vec![
+            (
+                200,
+                include_str!("../../tests/fixtures/gerrit/changes.json"),
+            ),
+            (
+                200,
+                include_str!("../../tests/fixtures/gerrit/reviewer_self.json"),
+            ),
+        ])
+        .await;
+        let account = account(Some(base.clone()), &["alice", "self"]);
+        let hub = Gerrit::new(&account, "platform".to_string()).unwrap();
+
+        let poll = hub.execute("bob:secret", "build").await.unwrap();
+        // Alice voted on 101, but the token's account is still asked to.
+        assert_eq!(
+            poll.reviews.iter().map(|r| r.number).collect::<Vec<_>>(),
+            vec![101, 102]
+        );
+        assert_eq!(
+            poll.reviews[1].link,
+            format!("{base}/c/platform/build/+/102")
+        );
+        assert_eq!(poll.open[0].reviewers, vec!["alice", "carol"]);
+
+        let authored = &poll.pulls[0];
+        assert_eq!((authored.number, authored.authored), (101, true));
+        assert_eq!(authored.mergeable.as_deref(), Some("dirty"));
+        assert_eq!(authored.ci, Some(CiState::Failure));
+        let reviews: Vec<_> = authored.reviews.values().collect();
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(
+            (reviews[0].user.as_str(), reviews[0].state),
+            ("alice", ReviewState::Approved)
+        );
+        assert_eq!(poll.pulls[1].ci, None);
+    }
+
+    #[test]
+    fn test_base_url() {
+        assert!(get_api(&account(None, &["alice"]), "platform".to_string()).is_err());
+        let account = account(Some("https://review.example.com/".to_string()), &[]);
+        let hub = Gerrit::new(&account, "platform".to_string()).unwrap();
+        assert_eq!(
+            hub.pull_request("build", 101),
+            "https://review.example.com/a/changes/platform%2Fbuild~101"
+        );
+    }
+}
@@ -15,53 +15,241 @@
  * limitations under the License.
  */
 
-use std::collections::HashMap;
 use std::string::String;
 
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION};
 use serde::Deserialize;
 
+use crate::conf::config::Account;
+use crate::conf::filter::{Candidate, Issue as IssueCandidate, IssueFilter};
+use crate::console::model::{Org, Repo};
+use crate::error::{AuthFailure, Error};
 use crate::notification::notify::notify;
-use crate::plugins::api::Api;
 use crate::plugins::api::PullRequest as PR;
-use crate::plugins::get_client;
+use crate::plugins::api::ReviewRequest as RR;
+use crate::plugins::api::{
+    self, Api, CiState, Event, OpenPull, Poll, PullState, Pulls, Review, ReviewState, Watch,
+};
+use crate::plugins::{get_client, get_pages};
 
 pub struct GitHub {
-    pub owner: String,
-    pub reviews: HashMap<String, ()>,
+    watch: Watch,
+    /// Commits behind base that make a PR stale, 0 to not compare.
+    behind: u64,
+    api: String,
+    web: String,
 }
 
 impl GitHub {
-    pub fn new(owner: String, reviews: HashMap<String, ()>) -> Self {
-        GitHub { owner, reviews }
-    }
-
-    pub async fn execute(&self, token: &str, repo: &str) -> Result<(), anyhow::Error> {
-        let prs =
-            get_client::<Vec<PullRequest>>(self.pull_requests(repo), self.headers(token)).await?;
-        for pr in prs {
-            let reviews =
-                get_client::<Reviews>(self.reviews(repo, pr.number), self.headers(token)).await?;
-            reviews.users.iter().for_each(|user| {
-                if self.reviews.contains_key(user.login.as_str()) {
-                    self.notify(
-                        repo,
-                        "",
-                        crate::plugins::api::PullRequest {
-                            title: pr.title.clone(),
-                            number: pr.number,
-                        },
-                    )
-                }
-            });
+    pub fn new(account: &Account, owner: String) -> Self {
+        // GitHub Enterprise serves the REST API under /api/v3 of its web address.
+        let (api, web) = match account.base_url.as_deref() {
+            Some(base) => {
+                let base = base.trim_end_matches('/');
+                (format!("{base}/api/v3"), base.to_string())
+            }
+            None => (
+                "https://api.github.com".to_string(),
+                "https://github.com".to_string(),
+            ),
+        };
+        GitHub {
+            watch: Watch::new(account, owner),
+            behind: if account.conflicts.enabled {
+                account.conflicts.behind
+            } else {
+                0
+            },
+            api,
+            web,
         }
-        Ok(())
+    }
+
+    /// Folds the commit statuses and check runs of `sha` into one state.
+    async fn ci_state(&self, token: &str, repo: &str, sha: &str) -> Result<Option<CiState>, Error> {
+        let status =
+            get_client::<CombinedStatus>(self.commit_status(repo, sha), self.headers(token))
+                .await?;
+        let url = format!("{}?per_page=100", self.check_runs(repo, sha));
+        // Fine-grained tokens without checks:read are refused, CI then reads statuses only.
+        let runs = match get_client::<CheckRuns>(url, self.headers(token)).await {
+            Ok(checks) => checks.check_runs,
+            Err(Error::Auth(AuthFailure::Forbidden(message))) => {
+                tracing::debug!(repo, "skipping check runs: {}", message);
+                vec![]
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(fold_ci(&status, &runs))
+    }
+
+    fn repos(&self) -> String {
+        format!(
+            "{}/users/{}/repos?page=1&per_page=100",
+            self.api, self.watch.owner
+        )
+    }
+
+    /// The token's own repos, private ones included.
+    fn own_repos(&self) -> String {
+        format!(
+            "{}/user/repos?affiliation=owner&page=1&per_page=100",
+            self.api
+        )
+    }
+
+    fn org_repos(&self) -> String {
+        format!(
+            "{}/orgs/{}/repos?page=1&per_page=100",
+            self.api, self.watch.owner
+        )
+    }
+
+    fn orgs(&self) -> String {
+        format!("{}/user/orgs", self.api)
+    }
+
+    fn pull_requests(&self, repo: &str) -> String {
+        format!("{}/repos/{}/{repo}/pulls", self.api, self.watch.owner)
+    }
+
+    fn issues(&self, repo: &str) -> String {
+        format!("{}/repos/{}/{repo}/issues", self.api, self.watch.owner)
+    }
+
+    fn issue_comments(&self, repo: &str) -> String {
+        format!("{}/comments", self.issues(repo))
+    }
+
+    /// Comments in the conversation of PR `number`, which GitHub keeps on its issue.
+    fn pull_comments(&self, repo: &str, number: i64) -> String {
+        format!("{}/{number}/comments?per_page=100", self.issues(repo))
+    }
+
+    fn pull_request(&self, repo: &str, number: i64) -> String {
+        format!("{}/{number}", self.pull_requests(repo))
+    }
+
+    /// Reviewers whose review is requested.
+    fn reviews(&self, repo: &str, number: i64) -> String {
+        format!("{}/requested_reviewers", self.pull_request(repo, number))
+    }
+
+    /// Reviews already submitted.
+    fn pull_reviews(&self, repo: &str, number: i64) -> String {
+        format!("{}/reviews", self.pull_request(repo, number))
+    }
+
+    /// Combined commit status of `sha`.
+    fn commit_status(&self, repo: &str, sha: &str) -> String {
+        format!(
+            "{}/repos/{}/{repo}/commits/{sha}/status",
+            self.api, self.watch.owner
+        )
+    }
+
+    fn check_runs(&self, repo: &str, sha: &str) -> String {
+        format!(
+            "{}/repos/{}/{repo}/commits/{sha}/check-runs",
+            self.api, self.watch.owner
+        )
+    }
+
+    fn compare(&self, repo: &str, base: &str, head: &str) -> String {
+        format!(
+            "{}/repos/{}/{repo}/compare/{base}...{head}",
+            self.api, self.watch.owner
+        )
     }
 }
 
+/// The state of a commit from its combined status and check runs, `None` without either.
+fn fold_ci(status: &CombinedStatus, runs: &[CheckRun]) -> Option<CiState> {
+    // Without any status the combined state still reads pending.
+    let state = (status.total_count > 0).then_some(match status.state.as_str() {
+        "success" => CiState::Success,
+        "pending" => CiState::Pending,
+        _ => CiState::Failure,
+    });
+    runs.iter()
+        .map(
+            |run| match (run.status.as_str(), run.conclusion.as_deref()) {
+                ("completed", Some("success" | "neutral" | "skipped")) => CiState::Success,
+                ("completed", _) => CiState::Failure,
+                _ => CiState::Pending,
+            },
+        )
+        .fold(state, |state, run| {
+            Some(state.map_or(run, |state| state.and(run)))
+        })
+}
+
 #[derive(Debug, Deserialize)]
 struct User {
     login: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Base {
+    #[serde(rename = "ref")]
+    branch: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    number: i64,
+    title: String,
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    labels: Vec<Label>,
+    #[serde(default)]
+    assignees: Vec<User>,
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comment {
+    id: u64,
+    html_url: String,
+    issue_url: String,
+    #[serde(default)]
+    body: String,
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+struct Size {
+    additions: u64,
+    deletions: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Detail {
+    mergeable_state: Option<String>,
+    #[serde(default)]
+    merged: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comparison {
+    behind_by: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmittedReview {
+    id: u64,
+    /// `None` once the reviewer's account was deleted.
+    user: Option<User>,
+    state: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -70,76 +258,432 @@ struct Reviews {
 }
 
 #[derive(Debug, Deserialize)]
-struct PullRequest {
+pub(crate) struct PullRequest {
     title: String,
     number: i64,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    labels: Vec<Label>,
+    user: User,
+    #[serde(default)]
+    requested_reviewers: Vec<User>,
+    base: Base,
+    head: Head,
+}
+
+#[derive(Debug, Deserialize)]
+struct Head {
+    sha: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct CombinedStatus {
+    state: String,
+    total_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRuns {
+    check_runs: Vec<CheckRun>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRun {
+    status: String,
+    conclusion: Option<String>,
+}
+
+#[async_trait]
+impl Pulls for GitHub {
+    type Pull = PullRequest;
+
+    fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    // Every page, a PR missing from the list reads as closed.
+    async fn pulls(&self, token: &str, repo: &str) -> Result<Vec<PullRequest>, Error> {
+        let url = format!("{}?per_page=100", self.pull_requests(repo));
+        get_pages::<PullRequest>(url, self.headers(token)).await
+    }
+
+    fn open(&self, repo: &str, pr: &PullRequest) -> OpenPull {
+        OpenPull {
+            number: pr.number,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.number),
+            draft: pr.draft,
+            reviewers: pr
+                .requested_reviewers
+                .iter()
+                .map(|user| user.login.clone())
+                .collect(),
+        }
+    }
+
+    fn author<'a>(&self, pr: &'a PullRequest) -> &'a str {
+        &pr.user.login
+    }
+
+    async fn reject(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &PullRequest,
+    ) -> Result<Option<String>, Error> {
+        let candidate = Candidate {
+            draft: pr.draft,
+            labels: pr.labels.iter().map(|label| label.name.as_str()).collect(),
+            author: pr.user.login.as_str(),
+            bot: pr.user.kind == "Bot" || pr.user.login.ends_with("[bot]"),
+            base: pr.base.branch.as_str(),
+        };
+        if let Some(reason) = self.watch.filter.reject(&candidate) {
+            return Ok(Some(reason));
+        }
+
+        let reviews =
+            get_client::<Reviews>(self.reviews(repo, pr.number), self.headers(token)).await?;
+        if !reviews
+            .users
+            .iter()
+            .any(|user| self.watch.reviews.contains_key(user.login.as_str()))
+        {
+            return Ok(Some("no watched reviewer requested".to_string()));
+        }
+
+        if self.watch.filter.checks_size() {
+            let size =
+                get_client::<Size>(self.pull_request(repo, pr.number), self.headers(token)).await?;
+            return Ok(self
+                .watch
+                .filter
+                .reject_size(size.additions + size.deletions));
+        }
+        Ok(None)
+    }
+
+    async fn pull_state(
+        &self,
+        token: &str,
+        repo: &str,
+        pr: &PullRequest,
+        authored: bool,
+    ) -> Result<PullState, Error> {
+        let mut pull = PullState {
+            number: pr.number,
+            title: pr.title.clone(),
+            link: self.link(repo, pr.number),
+            author: pr.user.login.clone(),
+            authored,
+            ..Default::default()
+        };
+        if self.watch.ci {
+            pull.ci = self.ci_state(token, repo, &pr.head.sha).await?;
+        }
+        if self.behind > 0 {
+            let url = self.compare(repo, &pr.base.branch, &pr.head.sha);
+            let comparison = get_client::<Comparison>(url, self.headers(token)).await?;
+            pull.behind = Some(comparison.behind_by);
+        }
+        if authored || self.watch.conflicts {
+            let detail =
+                get_client::<Detail>(self.pull_request(repo, pr.number), self.headers(token))
+                    .await?;
+            pull.mergeable = detail.mergeable_state.filter(|state| state != "unknown");
+        }
+        if !authored {
+            return Ok(pull);
+        }
+
+        let url = format!("{}?per_page=100", self.pull_reviews(repo, pr.number));
+        let reviews = get_client::<Vec<SubmittedReview>>(url, self.headers(token)).await?;
+        let comments =
+            get_pages::<Comment>(self.pull_comments(repo, pr.number), self.headers(token)).await?;
+        Ok(PullState {
+            reviews: reviews
+                .into_iter()
+                .filter_map(|review| {
+                    let state = match review.state.as_str() {
+                        "APPROVED" => ReviewState::Approved,
+                        "CHANGES_REQUESTED" => ReviewState::ChangesRequested,
+                        "COMMENTED" => ReviewState::Commented,
+                        "DISMISSED" => ReviewState::Dismissed,
+                        // PENDING reviews are drafts only their author can see.
+                        _ => return None,
+                    };
+                    let user = review.user?.login;
+                    Some((review.id, Review { user, state }))
+                })
+                .collect(),
+            comments: comments
+                .into_iter()
+                .map(|comment| (comment.id, comment.user.login))
+                .collect(),
+            ..pull
+        })
+    }
+}
+
+#[async_trait]
 impl Api for GitHub {
-    fn api(&self) -> &str {
-        "https://api.github.com"
+    #[tracing::instrument(name = "repo", skip(self, token), fields(owner = self.watch.owner))]
+    async fn execute(&self, token: &str, repo: &str) -> Result<Poll, Error> {
+        api::poll(self, token, repo).await
+    }
+
+    async fn preview(&self, token: &str, repo: &str) -> Result<Vec<(RR, Option<String>)>, Error> {
+        api::preview(self, token, repo).await
+    }
+
+    /// Whether the closed PR `number` was merged.
+    async fn merged(&self, token: &str, repo: &str, number: i64) -> Result<bool, Error> {
+        let detail =
+            get_client::<Detail>(self.pull_request(repo, number), self.headers(token)).await?;
+        Ok(detail.merged)
+    }
+
+    /// GitHub drops reviewers from the requested list once they review.
+    async fn reviewed(&self, token: &str, repo: &str, number: i64) -> Result<Vec<String>, Error> {
+        let url = format!("{}?per_page=100", self.pull_reviews(repo, number));
+        let reviews = get_client::<Vec<SubmittedReview>>(url, self.headers(token)).await?;
+        Ok(reviews
+            .into_iter()
+            .filter_map(|review| Some(review.user?.login))
+            .collect())
+    }
+
+    /// Open issues and recent comments on `repo` that the account's issue filter alerts on.
+    #[tracing::instrument(name = "issues", skip(self, token, filter, users), fields(owner = self.watch.owner))]
+    async fn watch_issues(
+        &self,
+        token: &str,
+        repo: &str,
+        filter: &IssueFilter,
+        users: &[String],
+    ) -> Result<Vec<Event>, Error> {
+        let url = format!("{}?state=open&sort=updated&per_page=100", self.issues(repo));
+        let issues = match get_client::<Vec<Issue>>(url, self.headers(token)).await {
+            // Repos with issues turned off answer 410 Gone.
+            Err(Error::Http(err)) if err.status == 410 => return Ok(vec![]),
+            issues => issues?,
+        };
+        let mut events = Vec::new();
+        for issue in &issues {
+            let candidate = IssueCandidate {
+                pull: issue.pull_request.is_some(),
+                assignees: issue.assignees.iter().map(|a| a.login.as_str()).collect(),
+                labels: issue.labels.iter().map(|l| l.name.as_str()).collect(),
+                body: issue.body.as_deref().unwrap_or_default(),
+            };
+            if let Some(reason) = filter.reason(users, &candidate) {
+                events.push(Event {
+                    account: self.watch.account.clone(),
+                    repo: repo.to_string(),
+                    number: issue.number,
+                    title: issue.title.clone(),
+                    link: issue.html_url.clone(),
+                    key: format!("{}:{reason}", issue.number),
+                    reason,
+                });
+            }
+        }
+
+        if filter.mentions {
+            let url = format!(
+                "{}?sort=updated&direction=desc&per_page=50",
+                self.issue_comments(repo)
+            );
+            for comment in get_client::<Vec<Comment>>(url, self.headers(token)).await? {
+                let Some(user) = filter.mentioned(users, &comment.body) else {
+                    continue;
+                };
+                // Comments only link their issue by URL, which ends with its number.
+                let Some(number) = comment
+                    .issue_url
+                    .rsplit('/')
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                else {
+                    continue;
+                };
+                let title = issues
+                    .iter()
+                    .find(|issue| issue.number == number)
+                    .map(|issue| issue.title.clone())
+                    .unwrap_or_default();
+                events.push(Event {
+                    account: self.watch.account.clone(),
+                    repo: repo.to_string(),
+                    number,
+                    title,
+                    link: comment.html_url,
+                    reason: format!("{user} mentioned by {}", comment.user.login),
+                    key: format!("{number}:comment:{}", comment.id),
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    async fn list_repos(&self, token: &str, org: bool) -> Result<Vec<Repo>, Error> {
+        let url = if org {
+            self.org_repos()
+        } else {
+            // Other users' listing only has public repos.
+            let me = get_client::<User>(self.user(), self.headers(token)).await?;
+            if me.login.eq_ignore_ascii_case(&self.watch.owner) {
+                self.own_repos()
+            } else {
+                self.repos()
+            }
+        };
+        get_pages::<Repo>(url, self.headers(token)).await
+    }
+
+    async fn list_orgs(&self, token: &str) -> Result<Vec<Org>, Error> {
+        get_pages::<Org>(self.orgs(), self.headers(token)).await
+    }
+
+    fn owner(&self) -> &str {
+        &self.watch.owner
     }
 
     fn headers(&self, token: &str) -> HeaderMap {
         let bearer_token = format!("Bearer {}", token);
-        let mut headers = HeaderMap::with_capacity(3);
+        let mut headers = HeaderMap::with_capacity(2);
         headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer_token).unwrap());
         headers.insert(
             ACCEPT,
             HeaderValue::from_static("application/vnd.github+json"),
         );
-        headers.insert(USER_AGENT, HeaderValue::from_static("Awesome-Octocat-App"));
         headers
     }
 
+    fn user(&self) -> String {
+        format!("{}/user", self.api)
+    }
+
     fn repo(&self, repo: &str) -> String {
-        format!("{}/users/{repo}/repos?page=1&per_page=100", self.api())
+        format!("{}/repos/{}/{repo}", self.api, self.watch.owner)
     }
 
-    fn repos(&self) -> String {
-        format!(
-            "{}/users/{}/repos?page=1&per_page=100",
-            self.api(),
-            self.owner
-        )
+    fn link(&self, repo: &str, number: i64) -> String {
+        format!("{}/{}/{repo}/pull/{number}", self.web, self.watch.owner)
     }
 
-    fn org_repos(&self) -> String {
-        format!(
-            "{}/orgs/{}/repos?page=1&per_page=100",
-            self.api(),
-            self.owner
+    fn notify(&self, repo: &str, _: &str, pr: PR) {
+        notify(
+            self.watch.account.as_str(),
+            pr.title.as_str(),
+            self.link(repo, pr.number).as_str(),
         )
     }
 
-    fn orgs(&self) -> String {
-        format!("{}/user/orgs", self.api())
+    fn client_id(&self) -> Option<String> {
+        option_env!("FLEXIBLE_GITHUB_CLIENT_ID").map(str::to_string)
     }
 
-    fn pull_requests(&self, repo: &str) -> String {
-        format!("{}/repos/{}/{repo}/pulls", self.api(), self.owner)
+    fn device_code_url(&self) -> Option<String> {
+        Some(format!("{}/login/device/code", self.web))
     }
 
-    fn issues(&self, repo: &str) -> String {
-        format!("{}/repos/{}/{repo}/issues", self.api(), self.owner)
+    fn access_token_url(&self) -> Option<String> {
+        Some(format!("{}/login/oauth/access_token", self.web))
     }
 
-    fn reviews(&self, repo: &str, number: i64) -> String {
-        format!(
-            "{}/repos/{}/{repo}/pulls/{number}/requested_reviewers",
-            self.api(),
-            self.owner
-        )
+    fn scopes(&self) -> Vec<String> {
+        vec!["repo".to_string(), "read:org".to_string()]
     }
-    fn notify(&self, repo: &str, _: &str, pr: PR) {
-        notify(
-            repo,
-            pr.title.as_str(),
-            format!(
-                "https://github.com/{}/{repo}/pull/{}",
-                self.owner, pr.number
-            )
-            .as_str(),
-        )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::conf::config::Account;
+    use crate::conf::filter::IssueFilter;
+    use crate::plugins::api::{Api, CiState};
+    use crate::plugins::github::{fold_ci, CheckRun, CombinedStatus, GitHub};
+    use crate::plugins::stub::serve;
+
+    fn status(state: &str, total_count: u64) -> CombinedStatus {
+        CombinedStatus {
+            state: state.to_string(),
+            total_count,
+        }
+    }
+
+    fn run(status: &str, conclusion: Option<&str>) -> CheckRun {
+        CheckRun {
+            status: status.to_string(),
+            conclusion: conclusion.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_fold_ci() {
+        assert_eq!(fold_ci(&status("pending", 0), &[]), None);
+        let runs = [
+            run("completed", Some("neutral")),
+            run("completed", Some("skipped")),
+        ];
+        assert_eq!(
+            fold_ci(&status("pending", 0), &runs),
+            Some(CiState::Success)
+        );
+        assert_eq!(
+            fold_ci(&status("success", 2), &runs),
+            Some(CiState::Success)
+        );
+        assert_eq!(
+            fold_ci(&status("success", 1), &[run("in_progress", None)]),
+            Some(CiState::Pending)
+        );
+        assert_eq!(
+            fold_ci(
+                &status("pending", 1),
+                &[run("completed", Some("timed_out"))]
+            ),
+            Some(CiState::Failure)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_runs_forbidden() {
+        let base = serve(vec![
+            (200, r#"{"state":"success","total_count":1}"#),
+            (
+                403,
+                r#"{"message":"Resource not accessible by personal access token"}"#,
+            ),
+        ])
+        .await;
+        let account = Account {
+            base_url: Some(base),
+            ..Default::default()
+        };
+        let hub = GitHub::new(&account, "apache".to_string());
+        assert_eq!(
+            hub.ci_state("token", "flexible", "abc").await,
+            Ok(Some(CiState::Success))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_issues_disabled() {
+        let base = serve(vec![(
+            410,
+            r#"{"message":"Issues are disabled for this repo"}"#,
+        )])
+        .await;
+        let account = Account {
+            base_url: Some(base),
+            ..Default::default()
+        };
+        let hub = GitHub::new(&account, "apache".to_string());
+        let events = hub
+            .watch_issues("token", "flexible", &IssueFilter::default(), &[])
+            .await;
+        assert!(events.is_ok_and(|events| events.is_empty()));
     }
 }
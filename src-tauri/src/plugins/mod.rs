@@ -15,35 +15,133 @@
  * limitations under the License.
  */
 
-use std::collections::HashMap;
+use std::sync::Mutex;
 
-use reqwest::header::HeaderMap;
-use reqwest::Error;
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::Response;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
+use crate::conf::config::Account;
+use crate::error::Error;
 use crate::plugins::api::Api;
 
-mod api;
+pub mod api;
+pub mod auth;
+pub mod azure;
+pub mod bitbucket;
+pub mod client;
+pub mod gerrit;
 pub mod github;
+pub mod oauth;
+pub mod response;
+#[cfg(test)]
+mod stub;
 
-pub fn get_api(api: &str, owner: String, reviews: Option<HashMap<String, ()>>) -> Box<dyn Api> {
-    match api {
-        "github" => Box::new(github::GitHub::new(owner, reviews.unwrap_or_default())),
-        _ => panic!("Unsupported"),
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RateLimit {
+    pub remaining: u64,
+    /// Unix seconds at which the quota resets.
+    pub reset: u64,
+}
+
+static RATE_LIMIT: Mutex<Option<RateLimit>> = Mutex::new(None);
+
+/// The quota reported by the most recent response that carried rate-limit headers.
+pub fn rate_limit() -> Option<RateLimit> {
+    *RATE_LIMIT.lock().unwrap()
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+pub fn get_api(account: &Account, owner: String) -> Result<Box<dyn Api>, Error> {
+    match account.plugin.as_str() {
+        "github" => Ok(Box::new(github::GitHub::new(account, owner))),
+        "bitbucket" => Ok(Box::new(bitbucket::Bitbucket::new(account, owner))),
+        "azure" => Ok(Box::new(azure::AzureDevOps::new(account, owner))),
+        "gerrit" => Ok(Box::new(gerrit::Gerrit::new(account, owner)?)),
+        api => Err(Error::Plugin(format!("unsupported plugin {api}"))),
+    }
+}
+
+/// Stops a misbehaving `Link` header from paging forever.
+pub(crate) const MAX_PAGES: usize = 50;
+
+#[tracing::instrument(
+    name = "http",
+    level = "debug",
+    skip(headers),
+    fields(url = response::redact_str(url), status)
+)]
+async fn send(url: &str, headers: HeaderMap) -> Result<Response, Error> {
+    let resp = client::shared().get(url).headers(headers).send().await?;
+    tracing::Span::current().record("status", resp.status().as_u16());
+    if let (Some(remaining), Some(reset)) = (
+        header(resp.headers(), "x-ratelimit-remaining"),
+        header(resp.headers(), "x-ratelimit-reset"),
+    ) {
+        *RATE_LIMIT.lock().unwrap() = Some(RateLimit { remaining, reset });
     }
+    response::check(resp).await
+}
+
+async fn decode<T>(url: &str, resp: Response) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let body = resp.bytes().await.map_err(|err| {
+        Error::Decode(format!(
+            "{}: {}",
+            response::redact_str(url),
+            err.without_url()
+        ))
+    })?;
+    // Gerrit prefixes its JSON with )]}' against cross-site script inclusion.
+    let json = body.strip_prefix(b")]}'").unwrap_or(&body);
+    serde_json::from_slice(json)
+        .map_err(|err| Error::Decode(format!("{}: {err}", response::redact_str(url))))
 }
 
 pub async fn get_client<T>(url: String, headers: HeaderMap) -> Result<T, Error>
 where
     T: DeserializeOwned,
 {
-    let resp = reqwest::Client::new()
-        .get(&url)
-        .headers(headers)
-        .timeout(std::time::Duration::from_secs(3))
-        .send()
-        .await?
-        .json::<T>()
-        .await?;
-    Ok(resp)
+    decode(&url, send(&url, headers).await?).await
+}
+
+/// Fetches `url` and every page its `Link: rel="next"` headers point to.
+pub async fn get_pages<T>(url: String, headers: HeaderMap) -> Result<Vec<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut items = Vec::new();
+    let mut next = Some(url);
+    for _ in 0..MAX_PAGES {
+        let Some(url) = next.take() else {
+            break;
+        };
+        let resp = send(&url, headers.clone()).await?;
+        next = next_link(resp.headers());
+        items.extend(decode::<Vec<T>>(&url, resp).await?);
+    }
+    Ok(items)
+}
+
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(LINK)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find_map(|link| {
+            let (url, params) = link.split_once(';')?;
+            params.contains(r#"rel="next""#).then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+        })
 }
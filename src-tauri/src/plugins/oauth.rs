@@ -0,0 +1,317 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one or more
+ * contributor license agreements.  See the NOTICE file distributed with
+ * this work for additional information regarding copyright ownership.
+ * The ASF licenses this file to You under the Apache License, Version 2.0
+ * (the "License"); you may not use this file except in compliance with
+ * the License.  You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use reqwest::header::{HeaderValue, ACCEPT};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::conf::config::{Account, ConfigData};
+use crate::conf::secret::{self, Credential};
+use crate::error::{AuthFailure, Error};
+use crate::plugins::{client, get_api, response};
+use crate::util;
+
+/// RFC 8628 asks clients to add five seconds whenever the server answers `slow_down`.
+const SLOW_DOWN: u64 = 5;
+
+/// Where and as which OAuth app to run the device flow.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub client_id: String,
+    pub device_url: String,
+    pub token_url: String,
+    pub scopes: Vec<String>,
+}
+
+impl Endpoints {
+    /// The plugin's endpoints, overridden by the account's `oauth` section.
+    pub fn resolve(account: &Account) -> Result<Self, Error> {
+        let api = get_api(account, "".to_string())?;
+        let unsupported = || Error::Plugin(format!("{} has no device sign in", account.plugin));
+        Ok(Endpoints {
+            client_id: account
+                .oauth
+                .client_id
+                .clone()
+                .or_else(|| api.client_id())
+                .ok_or_else(|| Error::Config("oauth.client_id: not set".to_string()))?,
+            device_url: account
+                .oauth
+                .device_url
+                .clone()
+                .or_else(|| api.device_code_url())
+                .ok_or_else(unsupported)?,
+            token_url: account
+                .oauth
+                .token_url
+                .clone()
+                .or_else(|| api.access_token_url())
+                .ok_or_else(unsupported)?,
+            scopes: account.oauth.scopes.clone().unwrap_or_else(|| api.scopes()),
+        })
+    }
+}
+
+/// What the user has to enter, and where, to approve the sign in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+enum Poll {
+    Pending,
+    SlowDown,
+    Done(Credential),
+}
+
+impl Token {
+    fn into_poll(self) -> Result<Poll, Error> {
+        if let Some(access_token) = self.access_token {
+            return Ok(Poll::Done(Credential {
+                access_token,
+                refresh_token: self.refresh_token,
+                expires_at: self.expires_in.map(|secs| util::now() + secs),
+            }));
+        }
+        let message = match self.error.as_deref() {
+            Some("authorization_pending") => return Ok(Poll::Pending),
+            Some("slow_down") => return Ok(Poll::SlowDown),
+            Some("expired_token") => "the code expired, start again".to_string(),
+            Some("access_denied") => "access was denied".to_string(),
+            error => self
+                .error_description
+                .or(error.map(str::to_string))
+                .unwrap_or_else(|| "no token in the response".to_string()),
+        };
+        Err(Error::Auth(AuthFailure::Login(message)))
+    }
+}
+
+async fn post<T>(url: &str, form: &[(&str, &str)]) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut resp = client::shared()
+        .post(url)
+        .header(ACCEPT, HeaderValue::from_static("application/json"))
+        .form(form)
+        .send()
+        .await?;
+    // Pending and denied logins come back as 400 with an OAuth error body.
+    if resp.status() != StatusCode::BAD_REQUEST {
+        resp = response::check(resp).await?;
+    }
+    resp.json::<T>().await.map_err(|err| {
+        Error::Decode(format!(
+            "{}: {}",
+            response::redact_str(url),
+            err.without_url()
+        ))
+    })
+}
+
+/// Asks the forge for a code the user approves in their browser.
+#[tracing::instrument(skip_all)]
+pub async fn start(endpoints: &Endpoints) -> Result<DeviceCode, Error> {
+    let scopes = endpoints.scopes.join(" ");
+    post(
+        &endpoints.device_url,
+        &[
+            ("client_id", endpoints.client_id.as_str()),
+            ("scope", scopes.as_str()),
+        ],
+    )
+    .await
+}
+
+/// Polls until the user approved or denied `code`, or it expired.
+#[tracing::instrument(skip_all)]
+pub async fn poll(endpoints: &Endpoints, code: &DeviceCode) -> Result<Credential, Error> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+    let mut interval = code.interval;
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if tokio::time::Instant::now() >= deadline {
+            return Err(Error::Auth(AuthFailure::Login(
+                "the code expired, start again".to_string(),
+            )));
+        }
+        let token: Token = post(
+            &endpoints.token_url,
+            &[
+                ("client_id", endpoints.client_id.as_str()),
+                ("device_code", code.device_code.as_str()),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ],
+        )
+        .await?;
+        match token.into_poll()? {
+            Poll::Pending => {}
+            Poll::SlowDown => interval += SLOW_DOWN,
+            Poll::Done(credential) => return Ok(credential),
+        }
+    }
+}
+
+async fn refresh(endpoints: &Endpoints, refresh_token: &str) -> Result<Credential, Error> {
+    let token: Token = post(
+        &endpoints.token_url,
+        &[
+            ("client_id", endpoints.client_id.as_str()),
+            ("refresh_token", refresh_token),
+            ("grant_type", "refresh_token"),
+        ],
+    )
+    .await?;
+    match token.into_poll()? {
+        Poll::Done(mut credential) => {
+            // Forges that do not rotate refresh tokens keep accepting the old one.
+            credential
+                .refresh_token
+                .get_or_insert_with(|| refresh_token.to_string());
+            Ok(credential)
+        }
+        _ => Err(Error::Auth(AuthFailure::Login(
+            "no token in the refresh response".to_string(),
+        ))),
+    }
+}
+
+/// The stored access token for `account`, refreshed first when it has expired.
+pub async fn stored_token(account: &Account) -> Result<Option<String>, Error> {
+    let Some(credential) = secret::load(account.name()).await? else {
+        return Ok(None);
+    };
+    if !credential.is_expired(util::now()) {
+        return Ok(Some(credential.access_token));
+    }
+    let Some(refresh_token) = credential.refresh_token.as_deref() else {
+        return Err(Error::Auth(AuthFailure::Login(
+            "the session expired, sign in again".to_string(),
+        )));
+    };
+    tracing::info!(account = account.name(), "refreshing the stored token");
+    let credential = refresh(&Endpoints::resolve(account)?, refresh_token).await?;
+    secret::store(account.name(), &credential).await?;
+    Ok(Some(credential.access_token))
+}
+
+/// Fills every empty `token` from the secret store, leaving it empty when nobody signed in.
+pub async fn resolve_tokens(conf: &mut ConfigData) -> Result<(), Error> {
+    for account in conf.accounts_mut() {
+        if !account.token.is_empty() {
+            continue;
+        }
+        if let Some(token) = stored_token(account).await? {
+            account.token = token;
+            account.signed_in = true;
+        }
+    }
+    Ok(())
+}
+
+/// The token to poll with now, which for a signed in account may have been refreshed since start.
+pub async fn token(account: &Account) -> Result<String, Error> {
+    if !account.signed_in {
+        return Ok(account.token.clone());
+    }
+    Ok(stored_token(account)
+        .await?
+        .unwrap_or_else(|| account.token.clone()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{AuthFailure, Error};
+    use crate::plugins::oauth::{poll, start, Endpoints};
+    use crate::plugins::stub::serve;
+
+    fn endpoints(base: &str) -> Endpoints {
+        Endpoints {
+            client_id: "client".to_string(),
+            device_url: format!("{base}/login/device/code"),
+            token_url: format!("{base}/login/oauth/access_token"),
+            scopes: vec!["repo".to_string(), "read:org".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_flow() {
+        let base = serve(vec![
+            (
+                200,
+                r#"{"device_code":"dc","user_code":"ABCD-1234","verification_uri":"https://github.com/login/device","expires_in":900,"interval":0}"#,
+            ),
+            (400, r#"{"error":"authorization_pending"}"#),
+            (
+                200,
+                r#"{"access_token":"at","refresh_token":"rt","expires_in":28800}"#,
+            ),
+        ])
+        .await;
+        let endpoints = endpoints(&base);
+
+        let code = start(&endpoints).await.unwrap();
+        assert_eq!(code.user_code, "ABCD-1234");
+        let credential = poll(&endpoints, &code).await.unwrap();
+        assert_eq!(credential.access_token, "at");
+        assert_eq!(credential.refresh_token.as_deref(), Some("rt"));
+        assert!(credential.expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_device_flow_denied() {
+        let base = serve(vec![
+            (
+                200,
+                r#"{"device_code":"dc","user_code":"ABCD-1234","verification_uri":"https://github.com/login/device","expires_in":900,"interval":0}"#,
+            ),
+            (200, r#"{"error":"access_denied"}"#),
+        ])
+        .await;
+        let endpoints = endpoints(&base);
+
+        let code = start(&endpoints).await.unwrap();
+        assert_eq!(
+            poll(&endpoints, &code).await,
+            Err(Error::Auth(AuthFailure::Login(
+                "access was denied".to_string()
+            )))
+        );
+    }
+}
@@ -15,20 +15,95 @@
  * limitations under the License.
  */
 
+use std::time::Duration;
+
+use flexible::dispatch::{self, Status};
+use flexible::error::Error;
 use tauri::{
-    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    AppHandle, CustomMenuItem, Icon, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
     SystemTrayMenuItem,
 };
 
+const MAX_REVIEWS: usize = 10;
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+const REVIEW_PREFIX: &str = "review:";
+
 pub fn menu() -> SystemTray {
-    let tray_menu = SystemTrayMenu::new()
-        .add_item(CustomMenuItem::new("reviews".to_string(), "New PR reviews"))
+    SystemTray::new().with_menu(build(&Status::default()))
+}
+
+fn build(status: &Status) -> SystemTrayMenu {
+    let reviews = &status.reviews;
+    let mut tray_menu = SystemTrayMenu::new().add_item(CustomMenuItem::new(
+        "reviews".to_string(),
+        format!("New PR reviews ({})", reviews.len()),
+    ));
+
+    for review in reviews.iter().take(MAX_REVIEWS) {
+        tray_menu = tray_menu.add_item(CustomMenuItem::new(
+            format!("{REVIEW_PREFIX}{}", review.link),
+            format!("{}#{} {}", review.repo, review.number, review.title),
+        ));
+    }
+    if reviews.len() > MAX_REVIEWS {
+        tray_menu = tray_menu.add_item(CustomMenuItem::new(
+            "more".to_string(),
+            format!("more… ({})", reviews.len() - MAX_REVIEWS),
+        ));
+    }
+
+    let pause = if status.paused { "Resume" } else { "Pause" };
+    tray_menu
         .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("pause".to_string(), pause))
         .add_item(CustomMenuItem::new("open".to_string(), "Open"))
         .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(CustomMenuItem::new("quit".to_string(), "Quit"));
+        .add_item(CustomMenuItem::new("quit".to_string(), "Quit"))
+}
 
-    SystemTray::new().with_menu(tray_menu)
+fn icon(status: &Status) -> Icon {
+    let bytes: &[u8] = if status.paused {
+        include_bytes!("../icons/tray-paused.png")
+    } else if status.error.as_ref().is_some_and(Error::is_fatal) {
+        include_bytes!("../icons/tray-error.png")
+    } else if !status.reviews.is_empty() {
+        include_bytes!("../icons/tray-pending.png")
+    } else {
+        include_bytes!("../icons/icon.png")
+    };
+    Icon::Raw(bytes.to_vec())
+}
+
+fn tooltip(status: &Status) -> String {
+    if status.paused {
+        "flexible: paused".to_string()
+    } else if let Some(err) = status.error.as_ref().filter(|err| err.is_fatal()) {
+        format!("flexible: {err}")
+    } else {
+        format!("flexible: {} pending reviews", status.reviews.len())
+    }
+}
+
+/// Rebuilds the tray whenever the dispatcher publishes a new status.
+pub fn watch(app: AppHandle) {
+    let mut rx = dispatch::subscribe();
+    tauri::async_runtime::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let status = rx.borrow_and_update().clone();
+            refresh(&app, &status);
+        }
+    });
+}
+
+fn refresh(app: &AppHandle, status: &Status) {
+    let tray = app.tray_handle();
+    _ = tray.set_menu(build(status));
+    _ = tray.set_icon(icon(status));
+    _ = tray.set_tooltip(tooltip(status).as_str());
+    #[cfg(target_os = "macos")]
+    {
+        _ = tray.set_title(status.reviews.len().to_string().as_str());
+    }
 }
 
 pub fn handler(app: &AppHandle, event: SystemTrayEvent) {
@@ -38,7 +113,14 @@ pub fn handler(app: &AppHandle, event: SystemTrayEvent) {
             position: _,
             size: _,
             ..
-        } => {}
+        } => {
+            if window.is_visible().unwrap_or(false) {
+                window.hide().unwrap();
+            } else {
+                window.show().unwrap();
+                _ = window.set_focus();
+            }
+        }
         SystemTrayEvent::RightClick {
             position: _,
             size: _,
@@ -46,15 +128,23 @@ pub fn handler(app: &AppHandle, event: SystemTrayEvent) {
         } => {}
         SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
             "quit" => {
-                std::process::exit(0);
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    dispatch::shutdown(SHUTDOWN_DEADLINE).await;
+                    app.exit(0);
+                });
+            }
+            "pause" => {
+                dispatch::pause(!dispatch::is_paused());
             }
-            "open" => {
+            "open" | "reviews" | "more" => {
                 window.show().unwrap();
             }
-            "reviews" => {
-                todo!()
+            id => {
+                if let Some(link) = id.strip_prefix(REVIEW_PREFIX) {
+                    _ = tauri::api::shell::open(&app.shell_scope(), link, None);
+                }
             }
-            _ => {}
         },
         _ => {}
     }
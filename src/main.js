@@ -1,35 +1,247 @@
 const {invoke} = window.__TAURI__.tauri;
+const {listen} = window.__TAURI__.event;
+const {open} = window.__TAURI__.shell;
 
 let greetMsgEl;
 
+const hints = {
+    config: "Please check the form.",
+    auth: "The token was rejected, check that it is valid and has the repo scope.",
+    rate_limit: "The API rate limit was hit, try a longer dispatch interval.",
+    network: "Could not reach the API, check your connection.",
+    decode: "The API returned an unexpected response.",
+    http: "The API rejected the request.",
+    plugin: "The selected policy is not supported.",
+    scheduler: "The watcher could not be started.",
+    secret: "The OS keychain is not available, paste a token instead.",
+};
+
+function showError(err) {
+    let p = document.createElement("p");
+    p.style.color = "red";
+    p.textContent = err.message + (hints[err.code] ? " " + hints[err.code] : "");
+    greetMsgEl.replaceChildren(p);
+}
+
 async function create(conf) {
-    let content = await invoke("create", {conf: conf})
-    if (content !== "") {
-        greetMsgEl.innerHTML = "<p style='color: red'>" + content + "</p>";
-    } else {
+    try {
+        await invoke("create", {conf: conf});
         greetMsgEl.textContent = "Success!";
+    } catch (err) {
+        showError(err);
     }
 }
 
+// Maps a validation path such as "orgs.apache[2]" to the input it came from.
+const fields = {
+    token: "#token",
+    plugin: "#policy",
+    dispatch: "#dispatch",
+    reviews: "#review",
+    "owners.name": "#owner",
+    "owners.repos": "#repos",
+    owners: "#owner",
+    orgs: "#org-repos",
+};
+
+function fieldFor(path) {
+    let key = Object.keys(fields)
+        .filter((k) => path === k || path.startsWith(k + "[") || path.startsWith(k + "."))
+        .sort((a, b) => b.length - a.length)[0];
+    return key && document.querySelector(fields[key]);
+}
+
+function showIssues(report) {
+    document.querySelectorAll(".issue").forEach((el) => el.remove());
+    for (const issue of report.issues) {
+        let span = document.createElement("span");
+        span.className = "issue " + issue.severity;
+        span.textContent = issue.path + ": " + issue.message;
+        let field = fieldFor(issue.path);
+        if (field) {
+            field.insertAdjacentElement("afterend", span);
+        } else {
+            greetMsgEl.appendChild(span);
+        }
+    }
+    return report.issues.every((issue) => issue.severity !== "error");
+}
+
+async function validate(conf, online) {
+    return showIssues(await invoke("validate_config", {conf: conf, online: online}));
+}
+
+function list(value) {
+    return value === "" ? [] : value.split(",");
+}
+
+function readConf() {
+    let org = document.querySelector("#org").value;
+    return {
+        plugin: document.querySelector("#policy").value,
+        token: document.querySelector("#token").value,
+        owners: {
+            name: document.querySelector("#owner").value,
+            repos: list(document.querySelector("#repos").value),
+        },
+        reviews: list(document.querySelector("#review").value),
+        dispatch: parseInt(document.querySelector("#dispatch").value),
+        orgs: org === "" ? {} : {[org]: list(document.querySelector("#org-repos").value)},
+    };
+}
+
+function time(secs) {
+    return secs ? new Date(secs * 1000).toLocaleTimeString() : "-";
+}
+
+function renderStatus(status) {
+    let state = status.paused ? "paused" : status.scheduler;
+    let summary = "Watcher " + state + ", " + status.reviews.length + " pending reviews"
+        + (status.events.length ? ", " + status.events.length + " alerts" : "")
+        + ", last tick " + time(status.last_tick);
+    if (status.last_duration_ms !== null) {
+        summary += " (" + status.last_duration_ms + "ms)";
+    }
+    summary += ", next tick " + time(status.next_tick);
+    if (status.rate_limit) {
+        summary += ", " + status.rate_limit.remaining + " API calls left until "
+            + time(status.rate_limit.reset);
+    }
+    if (status.error) {
+        summary += ". " + status.error.message;
+    }
+    document.querySelector("#status-summary").textContent = summary;
+
+    let table = document.querySelector("#status-repos");
+    table.replaceChildren();
+    for (const [repo, health] of Object.entries(status.repos)) {
+        let row = table.insertRow();
+        row.insertCell().textContent = repo;
+        row.insertCell().textContent = "ok " + time(health.last_success);
+        let failures = "";
+        if (health.consecutive_failures > 0) {
+            failures = health.consecutive_failures + " failures, " + health.last_error.message;
+        }
+        if (health.tripped) {
+            failures += ", paused until " + time(health.retry_at);
+        }
+        if (status.issues[repo]) {
+            failures += (failures ? ", " : "") + "issues: " + status.issues[repo].message;
+        }
+        row.insertCell().textContent = failures;
+    }
+}
+
+// Lists every open PR on the watched repos, greyed out with the reason when it is filtered.
+async function preview() {
+    let list = document.querySelector("#previews");
+    list.replaceChildren();
+    greetMsgEl.textContent = "";
+    let result = await invoke("preview_pulls", {conf: readConf()});
+    if (result.error) {
+        showError(result.error);
+        return;
+    }
+    for (const [repo, err] of Object.entries(result.data.errors)) {
+        let item = document.createElement("li");
+        item.textContent = repo + ": " + err.message;
+        item.classList.add("failed");
+        list.appendChild(item);
+    }
+    for (const pr of result.data.pulls) {
+        let item = document.createElement("li");
+        item.textContent = pr.repo + "#" + pr.number + " " + pr.title
+            + (pr.rejected ? " (" + pr.rejected + ")" : "");
+        item.classList.toggle("rejected", pr.rejected !== null);
+        list.appendChild(item);
+    }
+    if (list.children.length === 0) {
+        greetMsgEl.textContent = "No open PRs on the watched repos.";
+    }
+}
+
+// Sign in needs an OAuth app, without one the button is disabled and says why.
+async function checkLogin() {
+    let button = document.querySelector("#login");
+    try {
+        await invoke("login_available", {conf: readConf()});
+        button.disabled = false;
+        button.title = "";
+    } catch (err) {
+        button.disabled = true;
+        button.title = err.message;
+    }
+}
+
+// Shows the device code, then waits until it is approved in the browser.
+async function login() {
+    let code = document.querySelector("#login-code");
+    greetMsgEl.textContent = "";
+    try {
+        let conf = readConf();
+        let device = await invoke("start_login", {conf: conf});
+        let link = document.createElement("a");
+        link.href = "#";
+        link.textContent = device.verification_uri;
+        link.addEventListener("click", (e) => {
+            e.preventDefault();
+            open(device.verification_uri);
+        });
+        code.replaceChildren("Open ", link, " and enter " + device.user_code);
+        await invoke("finish_login", {conf: conf, code: device});
+        document.querySelector("#token").value = "";
+        code.textContent = "Signed in, the token is kept in the OS keychain.";
+    } catch (err) {
+        code.textContent = "";
+        showError(err);
+    }
+}
+
+async function refreshLogs() {
+    let logs = document.querySelector("#logs");
+    logs.textContent = (await invoke("get_logs", {limit: 200})).join("\n");
+    logs.scrollTop = logs.scrollHeight;
+}
+
 window.addEventListener("DOMContentLoaded", () => {
     greetMsgEl = document.querySelector("#greet-msg");
-    document.querySelector("#done").addEventListener("submit", (e) => {
+    invoke("watch_status").then(renderStatus);
+    listen("watch-status", (event) => renderStatus(event.payload));
+    let panel = document.querySelector("#log-panel");
+    panel.addEventListener("toggle", () => panel.open && refreshLogs());
+    setInterval(() => panel.open && refreshLogs(), 5000);
+    document.querySelector("#log-level").addEventListener("change", async (e) => {
+        try {
+            await invoke("set_log_level", {level: e.target.value});
+        } catch (err) {
+            showError(err);
+        }
+    });
+    document.querySelector("#login").addEventListener("click", login);
+    document.querySelector("#policy").addEventListener("change", checkLogin);
+    checkLogin();
+    document.querySelector("#preview").addEventListener("click", preview);
+    document.querySelector("#logout").addEventListener("click", async () => {
+        try {
+            await invoke("logout", {conf: readConf()});
+            document.querySelector("#login-code").textContent = "Signed out.";
+        } catch (err) {
+            showError(err);
+        }
+    });
+    document.querySelector("#check").addEventListener("click", async () => {
+        greetMsgEl.textContent = "";
+        if (await validate(readConf(), true)) {
+            greetMsgEl.textContent = "Config looks good.";
+        }
+    });
+    document.querySelector("#done").addEventListener("submit", async (e) => {
         e.preventDefault();
 
-        let token = document.querySelector("#token").value;
-        let owner = document.querySelector("#owner").value;
-        let repos = document.querySelector("#repos").value.split(",");
-        let review = document.querySelector("#review").value.split(",");
-        let policy = document.querySelector("#policy").value;
-        let dispatch = document.querySelector("#dispatch").value;
-        let org = document.querySelector("#org").value;
-        let org_repos = document.querySelector("#org-repos").value.split(",");
-
-        let conf = {
-            plugin: policy, token: token, owners: {
-                name: owner, repos: repos,
-            }, reviews: review, dispatch: parseInt(dispatch), orgs: new Map().set(org, org_repos),
-        };
-        create(conf);
+        greetMsgEl.textContent = "";
+        let conf = readConf();
+        if (await validate(conf, false)) {
+            create(conf);
+        }
     });
 });